@@ -0,0 +1,52 @@
+#![feature(test)]
+
+extern crate json_parser;
+extern crate test;
+
+use json_parser::Parse;
+use test::Bencher;
+
+/// An object with many members.
+fn wide_object() -> String {
+    let members: Vec<_> = (0..10_000).map(|i| format!(r#""key{}": {}"#, i, i)).collect();
+    format!("{{{}}}", members.join(", "))
+}
+
+/// Arrays nested inside each other.
+fn deep_nesting() -> String {
+    format!("{}{}", "[".repeat(1_000), "]".repeat(1_000))
+}
+
+/// An array of long strings, mostly without escape sequences.
+fn long_strings() -> String {
+    let string = format!(r#""{}\n{}""#, "lorem ipsum dolor sit amet ".repeat(40), "é".repeat(10));
+    let strings: Vec<_> = (0..1_000).map(|_| &string[..]).collect();
+    format!("[{}]", strings.join(", "))
+}
+
+fn bench_value(b: &mut Bencher, source: &str) {
+    b.bytes = source.len() as u64;
+    b.iter(|| Parse::new(source).value().unwrap());
+}
+
+#[bench]
+fn wide_object_value(b: &mut Bencher) {
+    bench_value(b, &wide_object());
+}
+
+#[bench]
+fn deep_nesting_value(b: &mut Bencher) {
+    bench_value(b, &deep_nesting());
+}
+
+#[bench]
+fn long_strings_value(b: &mut Bencher) {
+    bench_value(b, &long_strings());
+}
+
+#[bench]
+fn long_strings_validate(b: &mut Bencher) {
+    let source = long_strings();
+    b.bytes = source.len() as u64;
+    b.iter(|| json_parser::validate(&source).unwrap());
+}
@@ -0,0 +1,35 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate json_parser;
+
+use std::str;
+use json_parser::Options;
+use json_parser::lex::{Lex, TokenKind};
+
+/// Lex the input to the end, checking that every token makes progress and that spans are
+/// adjacent slices of the input.
+fn lex(source: &str, options: Options) {
+    let mut lex = Lex::with_options(source, options);
+    let mut position = 0;
+    loop {
+        let token = lex.token();
+        let offset = lex.offset(token.span);
+        assert!(offset >= position && offset + token.span.len() <= source.len());
+        assert_eq!(&source[offset..offset + token.span.len()], token.span);
+        position = offset + token.span.len();
+
+        if token.kind == TokenKind::End {
+            assert_eq!(position, source.len());
+            break;
+        }
+        assert!(!token.span.is_empty(), "token {:?} made no progress", token);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = str::from_utf8(data) {
+        lex(source, Options::default());
+        lex(source, Options::strict());
+        lex(source, Options::lenient());
+    }
+});
@@ -0,0 +1,182 @@
+use lex::{Lex, TokenKind};
+use options::Options;
+use parse::{Parse, ParseError};
+
+/// Options for `format`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FormatOptions {
+    /// The number of spaces for each level of nesting.
+    pub indent: usize,
+    /// Accept `//` and `/* */` comments, as with `Options::allow_comments`, and keep them in the
+    /// output.
+    pub allow_comments: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions { indent: 2, allow_comments: false }
+    }
+}
+
+/// Reformat a JSON document, normalizing its whitespace.
+///
+/// This works on the token stream rather than a parsed value, so object keys stay in their
+/// original order and strings and numbers keep their original source text.
+/// Each object member and array element goes on its own line, with a space after each colon.
+/// Empty objects and arrays are written as `{}` and `[]`.
+///
+/// With `FormatOptions::allow_comments`, each comment stays between the same two tokens. A line
+/// comment ends its line, and a block comment is followed by a space.
+pub fn format<'source>(source: &'source str, options: FormatOptions) -> Result<String, ParseError<'source>> {
+    let parse_options = Options { allow_comments: options.allow_comments, ..Options::default() };
+
+    // Only valid documents are formatted, so the token stream below is well-formed.
+    Parse::with_options(source, parse_options).validate()?;
+
+    // Pair each token with the comments before it, including those at the end of the input.
+    let mut tokens = Vec::new();
+    let mut lex = Lex::with_options(source, parse_options);
+    loop {
+        let token = lex.token();
+        let end = token.kind == TokenKind::End;
+        tokens.push((token, lex.comments().to_vec()));
+        if end {
+            break;
+        }
+    }
+
+    let mut out = String::with_capacity(source.len());
+    let mut depth = 0;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some((token, before)) = tokens.next() {
+        comments(&mut out, &before, depth * options.indent);
+        match token.kind {
+            TokenKind::LeftBrace | TokenKind::LeftBracket => {
+                out.push_str(token.span);
+                let empty = match tokens.peek() {
+                    Some((next, before)) => before.is_empty() &&
+                        (next.kind == TokenKind::RightBrace || next.kind == TokenKind::RightBracket),
+                    None => false,
+                };
+                if empty {
+                    let (close, _) = tokens.next().unwrap();
+                    out.push_str(close.span);
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth * options.indent);
+                }
+            }
+            TokenKind::RightBrace | TokenKind::RightBracket => {
+                depth -= 1;
+                newline(&mut out, depth * options.indent);
+                out.push_str(token.span);
+            }
+            TokenKind::Comma => {
+                out.push_str(token.span);
+                newline(&mut out, depth * options.indent);
+            }
+            TokenKind::Colon => {
+                out.push_str(token.span);
+                out.push(' ');
+            }
+            _ => out.push_str(token.span),
+        }
+    }
+    let len = out.trim_end().len();
+    out.truncate(len);
+    Ok(out)
+}
+
+/// Remove all insignificant whitespace from a JSON document.
+///
+/// Like `format`, this works on the token stream, so keys keep their order and strings and
+/// numbers keep their source text, and no value is built.
+pub fn minify<'source>(source: &'source str) -> Result<String, ParseError<'source>> {
+    Parse::new(source).validate()?;
+
+    let mut out = String::with_capacity(source.len());
+    let mut lex = Lex::new(source);
+    lex.raw_strings();
+    for token in lex {
+        out.push_str(token.span);
+    }
+    Ok(out)
+}
+
+/// Write the comments before a token, separated from the preceding output by a space.
+fn comments(out: &mut String, comments: &[&str], indent: usize) {
+    for comment in comments {
+        if !out.is_empty() && !out.ends_with(' ') && !out.ends_with('\n') {
+            out.push(' ');
+        }
+        out.push_str(comment);
+        if comment.starts_with("//") {
+            newline(out, indent);
+        } else {
+            out.push(' ');
+        }
+    }
+}
+
+/// Start a new line at `indent`, unless the output already ends with one, such as after a line
+/// comment.
+fn newline(out: &mut String, indent: usize) {
+    let len = out.trim_end_matches(' ').len();
+    out.truncate(len);
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    for _ in 0..indent {
+        out.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use format::{format, minify, FormatOptions};
+
+    #[test]
+    fn format_document() {
+        let s = r#"{"b":[1.50,"A",{}],   "a" : { "c":[ ] } }"#;
+        let formatted = format(s, FormatOptions::default()).unwrap();
+        assert_eq!(formatted, r#"{
+  "b": [
+    1.50,
+    "A",
+    {}
+  ],
+  "a": {
+    "c": []
+  }
+}"#);
+
+        assert!(format("[1,]", FormatOptions::default()).is_err());
+    }
+
+    #[test]
+    fn format_comments() {
+        let s = "// config\n{\"a\": /* one */ 1, // after\n \"b\":[/* empty */]} /* end */";
+        let options = FormatOptions { allow_comments: true, ..FormatOptions::default() };
+        assert_eq!(format(s, options).unwrap(), r#"// config
+{
+  "a": /* one */ 1,
+  // after
+  "b": [
+    /* empty */
+  ]
+} /* end */"#);
+        assert_eq!(format("[1 // one\n]", options).unwrap(), "[\n  1 // one\n]");
+
+        assert!(format(s, FormatOptions::default()).is_err());
+    }
+
+    #[test]
+    fn minify_document() {
+        let s = "{\n  \"b\": [1.50, \"A \\u0042\", {}],\r\n\t\"a\" : { \"c\":[ ] }\n}\n";
+        assert_eq!(minify(s).unwrap(), r#"{"b":[1.50,"A \u0042",{}],"a":{"c":[]}}"#);
+        let formatted = format(s, FormatOptions::default()).unwrap();
+        assert_eq!(minify(&formatted).unwrap(), minify(s).unwrap());
+
+        assert!(minify("[1,]").is_err());
+    }
+}
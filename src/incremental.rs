@@ -0,0 +1,380 @@
+use std::{mem, result, str};
+use lex::{Lex, TokenKind};
+use parse::{Parse, ParseErrorKind};
+use options::Options;
+use json::{Value, FromStrError};
+
+/// A parser for a stream of JSON values that arrives in pieces, such as from a socket.
+///
+/// Values in the stream may be separated by whitespace, and by comments with
+/// `Options::allow_comments`. With `Options::allow_hash_line`, only the first line of the
+/// whole stream may be a `#` line. Input is buffered until it holds a
+/// complete value, which is then parsed as a whole, so a value may be split across `feed`s at
+/// any byte, including in the middle of a token or a UTF-8 codepoint.
+///
+/// A number, boolean or null at the top level is only known to be complete once the byte after
+/// it arrives, so the last value in a stream may need `finish` to produce it.
+pub struct IncrementalParser {
+    options: Options,
+    buffer: Vec<u8>,
+    /// Whether the stream may still begin with a byte order mark.
+    bom: bool,
+    /// Whether the stream may still begin with a `#` line.
+    hash_line: bool,
+
+    /// How much of the buffer has been scanned for the end of the next value. Values before
+    /// this that are already complete are only removed at the end of each `feed`.
+    scanned: usize,
+    /// Whether the scan has reached the first byte of the next value.
+    started: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    in_line_comment: bool,
+    in_block_comment: bool,
+}
+
+type Result<T> = result::Result<T, FromStrError>;
+
+impl IncrementalParser {
+    /// Create a new incremental parser.
+    pub fn new() -> IncrementalParser {
+        Self::with_options(Options::default())
+    }
+
+    /// Create a new incremental parser, with the given options.
+    pub fn with_options(options: Options) -> IncrementalParser {
+        IncrementalParser {
+            // Values after the start of the stream never begin with a `#` line.
+            options: Options { allow_hash_line: false, ..options },
+            buffer: Vec::new(),
+            bom: options.allow_bom,
+            hash_line: options.allow_hash_line,
+            scanned: 0,
+            started: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            in_line_comment: false,
+            in_block_comment: false,
+        }
+    }
+
+    /// Add the next piece of the stream, and parse every value it completes.
+    ///
+    /// An invalid value produces an error in its place, and parsing resumes after it.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Result<Value>> {
+        self.buffer.extend_from_slice(bytes);
+
+        if self.bom {
+            const BOM: &[u8] = b"\xEF\xBB\xBF";
+            if self.buffer.len() < BOM.len() && BOM.starts_with(&self.buffer) {
+                return Vec::new();
+            }
+            if self.buffer.starts_with(BOM) {
+                self.buffer.drain(..BOM.len());
+            }
+            self.bom = false;
+        }
+        if self.hash_line {
+            match self.buffer.first() {
+                Some(&b'#') => match self.buffer.iter().position(|&b| b == b'\n') {
+                    Some(newline) => { self.buffer.drain(..newline + 1); }
+                    None => return Vec::new(),
+                },
+                Some(_) => {}
+                None => return Vec::new(),
+            }
+            self.hash_line = false;
+        }
+
+        let mut values = Vec::new();
+        let mut start = 0;
+        while let Some(end) = self.scan() {
+            values.push(self.parse(&self.buffer[start..end]));
+            start = end;
+        }
+        // Drop every completed value at once, rather than moving the rest of the buffer after
+        // each one.
+        self.buffer.drain(..start);
+        self.scanned -= start;
+        values
+    }
+
+    /// End the stream, and parse any value left in the buffer.
+    ///
+    /// Incomplete objects, arrays and strings produce an error, but whitespace and comments
+    /// after the last value do not.
+    pub fn finish(&mut self) -> Option<Result<Value>> {
+        let source = mem::take(&mut self.buffer);
+        self.reset();
+        self.bom = false;
+        if mem::replace(&mut self.hash_line, false) && source.first() == Some(&b'#') {
+            // The whole stream is a `#` line without its newline.
+            return None;
+        }
+        let empty = match str::from_utf8(&source) {
+            Ok(source) => Lex::with_options(source, self.options).token().kind == TokenKind::End,
+            Err(_) => false,
+        };
+        if empty {
+            return None;
+        }
+        Some(self.parse(&source))
+    }
+
+    /// Continue scanning the buffer, and return the end of the next value if it is complete.
+    ///
+    /// Every byte this looks for is ASCII, so the end of a value is always on a codepoint
+    /// boundary.
+    fn scan(&mut self) -> Option<usize> {
+        while self.scanned < self.buffer.len() {
+            let b = self.buffer[self.scanned];
+            let end = self.scanned;
+            self.scanned += 1;
+
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        if self.depth == 0 {
+                            return Some(self.complete(self.scanned));
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if self.in_line_comment {
+                self.in_line_comment = b != b'\n';
+                continue;
+            }
+            if self.in_block_comment {
+                if b == b'*' {
+                    match self.buffer.get(self.scanned) {
+                        Some(&b'/') => { self.scanned += 1; self.in_block_comment = false; }
+                        Some(_) => {}
+                        // Wait for the byte after the `*`.
+                        None => { self.scanned = end; return None; }
+                    }
+                }
+                continue;
+            }
+            if b == b'/' && self.options.allow_comments {
+                match self.buffer.get(self.scanned) {
+                    Some(&b'/') | Some(&b'*') if self.depth == 0 && self.started => {
+                        // A comment ends a top-level scalar, like whitespace.
+                        return Some(self.complete(end));
+                    }
+                    Some(&b'/') => { self.scanned += 1; self.in_line_comment = true; continue; }
+                    Some(&b'*') => { self.scanned += 1; self.in_block_comment = true; continue; }
+                    Some(_) => {}
+                    // Wait for the byte after the `/`.
+                    None => { self.scanned = end; return None; }
+                }
+            }
+
+            match b {
+                b'"' | b'{' | b'[' | b'}' | b']' | b',' | b':' if self.depth == 0 && self.started => {
+                    // The end of a top-level scalar, which the next value starts right after.
+                    return Some(self.complete(end));
+                }
+                _ if self.is_whitespace(b) && self.depth == 0 && self.started => {
+                    return Some(self.complete(end));
+                }
+                _ if self.is_whitespace(b) => {}
+
+                b'"' => { self.started = true; self.in_string = true; }
+                b'{' | b'[' => { self.started = true; self.depth += 1; }
+                b'}' | b']' | b',' | b':' if self.depth == 0 => {
+                    // A lone delimiter, which the parser will reject.
+                    return Some(self.complete(self.scanned));
+                }
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(self.complete(self.scanned));
+                    }
+                }
+                _ => self.started = true,
+            }
+        }
+        None
+    }
+
+    /// Whether a byte is whitespace between tokens, by the same options as the lexer.
+    fn is_whitespace(&self, b: u8) -> bool {
+        match b {
+            b' ' | b'\t' | b'\r' | b'\n' => true,
+            b'\x0C' | b'\x0B' => self.options.allow_extra_whitespace,
+            _ => false,
+        }
+    }
+
+    fn complete(&mut self, end: usize) -> usize {
+        self.reset();
+        self.scanned = end;
+        end
+    }
+
+    fn reset(&mut self) {
+        self.scanned = 0;
+        self.started = false;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        self.in_line_comment = false;
+        self.in_block_comment = false;
+    }
+
+    fn parse(&self, source: &[u8]) -> Result<Value> {
+        match str::from_utf8(source) {
+            Ok(source) => Ok(Parse::with_options(source, self.options).value()?),
+            Err(_) => Err(FromStrError {
+                kind: ParseErrorKind::UnexpectedToken,
+                lex_error: None,
+                span: String::from_utf8_lossy(source).into_owned(),
+            }),
+        }
+    }
+}
+
+impl Default for IncrementalParser {
+    fn default() -> IncrementalParser {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use incremental::IncrementalParser;
+    use json::Value;
+    use options::Options;
+    use parse::ParseErrorKind;
+
+    #[test]
+    fn feed() {
+        let stream = "{ \"a\": [1, \"]\\\"\"] } \"é\" [] 12 true{} 3";
+        let expected = vec![
+            r#"{ "a": [1, "]\""] }"#.parse::<Value>().unwrap(),
+            Value::from("é"),
+            Value::array().build(),
+            Value::Number(12.0),
+            Value::Bool(true),
+            Value::object().build(),
+            Value::Number(3.0),
+        ];
+
+        // Split the stream at every possible byte, including inside `é`.
+        for split in 0..=stream.len() {
+            let (a, b) = stream.as_bytes().split_at(split);
+            let mut parser = IncrementalParser::new();
+            let mut values = parser.feed(a);
+            values.extend(parser.feed(b));
+            values.extend(parser.finish());
+            let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, expected);
+        }
+
+        // One byte at a time.
+        let mut parser = IncrementalParser::new();
+        let mut values = Vec::new();
+        for b in stream.as_bytes() {
+            values.extend(parser.feed(&[*b]));
+        }
+        assert_eq!(values.len(), 6);
+        values.extend(parser.finish());
+        assert_eq!(values.into_iter().map(Result::unwrap).collect::<Vec<_>>(), expected);
+        assert_eq!(parser.finish(), None);
+
+        // Only the incomplete value is left in the buffer.
+        let mut parser = IncrementalParser::new();
+        assert_eq!(parser.feed(b"[1] [2] [3").len(), 2);
+        assert_eq!(parser.buffer, b" [3");
+        assert_eq!(parser.feed(b"]"), vec![Ok(Value::array().push(3.0.into()).build())]);
+        assert!(parser.buffer.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let mut parser = IncrementalParser::new();
+        let values = parser.feed(b"[1,] ] 2 \xFF ");
+        assert_eq!(values[0].as_ref().unwrap_err().span, "]");
+        assert_eq!(values[1].as_ref().unwrap_err().span, "]");
+        assert_eq!(values[2], Ok(Value::Number(2.0)));
+        assert_eq!(values[3].as_ref().unwrap_err().kind, ParseErrorKind::UnexpectedToken);
+        assert_eq!(values.len(), 4);
+
+        parser.feed(b"{ \"a\": ");
+        assert_eq!(parser.finish().unwrap().unwrap_err().span, "");
+    }
+
+    #[test]
+    fn comments() {
+        let stream = "{\"a\": 1 /* } \" */} // {\n2/* c */3 /* end */";
+        let expected = vec![
+            r#"{"a": 1}"#.parse::<Value>().unwrap(),
+            Value::Number(2.0),
+            Value::Number(3.0),
+        ];
+        let options = Options { allow_comments: true, ..Options::default() };
+        for split in 0..=stream.len() {
+            let (a, b) = stream.as_bytes().split_at(split);
+            let mut parser = IncrementalParser::with_options(options);
+            let mut values = parser.feed(a);
+            values.extend(parser.feed(b));
+            values.extend(parser.finish());
+            let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[test]
+    fn extra_whitespace() {
+        let mut parser = IncrementalParser::with_options(Options::lenient());
+        let values = parser.feed(b"1\x0C2\x0Btrue\x0C");
+        let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+        assert_eq!(values, vec![Value::Number(1.0), Value::Number(2.0), Value::Bool(true)]);
+        assert_eq!(parser.finish(), None);
+
+        // Without the option, the form feed is part of an invalid token.
+        let mut parser = IncrementalParser::new();
+        assert_eq!(parser.feed(b"1\x0C2 ")[0].as_ref().unwrap_err().span, "\x0C2");
+    }
+
+    #[test]
+    fn hash_line() {
+        let stream = "#!/usr/bin/tool [1]\n[2] 3";
+        let options = Options { allow_hash_line: true, ..Options::default() };
+        for split in 0..=stream.len() {
+            let (a, b) = stream.as_bytes().split_at(split);
+            let mut parser = IncrementalParser::with_options(options);
+            let mut values = parser.feed(a);
+            values.extend(parser.feed(b));
+            values.extend(parser.finish());
+            let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+            assert_eq!(values, vec![Value::array().push(2.0.into()).build(), Value::Number(3.0)]);
+        }
+
+        let mut parser = IncrementalParser::with_options(options);
+        assert_eq!(parser.feed(b"#!tool"), vec![]);
+        assert_eq!(parser.finish(), None);
+
+        // Only the first line of the stream may be a `#` line.
+        let mut parser = IncrementalParser::with_options(options);
+        assert!(parser.feed(b"[]\n#x\n")[1].is_err());
+        assert!(IncrementalParser::new().feed(b"#x\n[]")[0].is_err());
+    }
+
+    #[test]
+    fn bom() {
+        let mut parser = IncrementalParser::new();
+        assert_eq!(parser.feed(b"\xEF\xBB"), vec![]);
+        assert_eq!(parser.feed(b"\xBF[]"), vec![Ok(Value::array().build())]);
+
+        let mut parser = IncrementalParser::with_options(Options::strict());
+        assert!(parser.feed(b"\xEF\xBB\xBF[]")[0].is_err());
+    }
+}
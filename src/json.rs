@@ -1,9 +1,22 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+use std::{error, fmt, mem, str};
+use std::convert::TryFrom;
+use parse::{self, Parse, ParseErrorKind, Comments};
+use lex::LexErrorKind;
 
 /// A JSON value.
+#[derive(Clone, PartialEq, Debug)]
 pub enum Value {
     String(String),
     Number(f64),
+    /// An integer, from `NumberMode::Integer`.
+    Integer(i64),
+    /// A number kept as its original source text, for lossless round-tripping, from
+    /// `NumberMode::Raw` or `NumberMode::BigInteger`.
+    RawNumber(String),
     Bool(bool),
     Null,
     Object(Object),
@@ -13,3 +26,2045 @@ pub enum Value {
 pub type Object = HashMap<String, Value>;
 
 pub type Array = Vec<Value>;
+
+/// A JSON value that borrows its strings and object keys from the source, from
+/// `Parse::borrowed_value`.
+///
+/// Strings with escape sequences must be decoded, so they are owned instead.
+#[derive(Clone, PartialEq, Debug)]
+pub enum BorrowedValue<'source> {
+    String(Cow<'source, str>),
+    Number(f64),
+    Integer(i64),
+    RawNumber(String),
+    Bool(bool),
+    Null,
+    Object(BorrowedObject<'source>),
+    Array(BorrowedArray<'source>),
+}
+
+pub type BorrowedObject<'source> = HashMap<Cow<'source, str>, BorrowedValue<'source>>;
+
+pub type BorrowedArray<'source> = Vec<BorrowedValue<'source>>;
+
+impl<'source> BorrowedValue<'source> {
+    /// Convert this value to a `Value`, copying any borrowed strings.
+    pub fn into_owned(self) -> Value {
+        match self {
+            BorrowedValue::String(string) => Value::String(string.into_owned()),
+            BorrowedValue::Number(number) => Value::Number(number),
+            BorrowedValue::Integer(integer) => Value::Integer(integer),
+            BorrowedValue::RawNumber(number) => Value::RawNumber(number),
+            BorrowedValue::Bool(bool_) => Value::Bool(bool_),
+            BorrowedValue::Null => Value::Null,
+            BorrowedValue::Object(object) => {
+                Value::Object(object.into_iter().map(|(key, value)| (key.into_owned(), value.into_owned())).collect())
+            }
+            BorrowedValue::Array(array) => Value::Array(array.into_iter().map(BorrowedValue::into_owned).collect()),
+        }
+    }
+}
+
+/// A JSON value whose object keys are shared between objects, from `Parse::interned_value`.
+///
+/// Each distinct key is allocated once per document, which saves memory for arrays of records
+/// with the same keys.
+#[derive(Clone, PartialEq, Debug)]
+pub enum InternedValue {
+    String(String),
+    Number(f64),
+    Integer(i64),
+    RawNumber(String),
+    Bool(bool),
+    Null,
+    Object(InternedObject),
+    Array(InternedArray),
+}
+
+pub type InternedObject = HashMap<Rc<str>, InternedValue>;
+
+pub type InternedArray = Vec<InternedValue>;
+
+impl InternedValue {
+    /// Convert this value to a `Value`, copying each key.
+    pub fn into_owned(self) -> Value {
+        match self {
+            InternedValue::String(string) => Value::String(string),
+            InternedValue::Number(number) => Value::Number(number),
+            InternedValue::Integer(integer) => Value::Integer(integer),
+            InternedValue::RawNumber(number) => Value::RawNumber(number),
+            InternedValue::Bool(bool_) => Value::Bool(bool_),
+            InternedValue::Null => Value::Null,
+            InternedValue::Object(object) => {
+                Value::Object(object.into_iter().map(|(key, value)| (String::from(&*key), value.into_owned())).collect())
+            }
+            InternedValue::Array(array) => Value::Array(array.into_iter().map(InternedValue::into_owned).collect()),
+        }
+    }
+}
+
+/// A JSON value whose strings, objects and arrays are reference-counted, so its clones share
+/// them rather than copying them.
+///
+/// Cloning is cheap, which suits keeping many versions of a document. Mutation goes through
+/// `get_mut`, `get_index_mut` and `pointer_mut`, which copy each shared object or array on the
+/// way down to the value they return, so other clones are unchanged.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RcValue {
+    String(Rc<str>),
+    Number(f64),
+    Integer(i64),
+    RawNumber(Rc<str>),
+    Bool(bool),
+    Null,
+    Object(Rc<RcObject>),
+    Array(Rc<RcArray>),
+}
+
+pub type RcObject = HashMap<String, RcValue>;
+
+pub type RcArray = Vec<RcValue>;
+
+impl RcValue {
+    /// Borrow the member of an object with the given key.
+    pub fn get(&self, key: &str) -> Option<&RcValue> {
+        match *self {
+            RcValue::Object(ref object) => object.get(key),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow the member of an object with the given key, first copying the object if
+    /// it is shared.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut RcValue> {
+        match *self {
+            RcValue::Object(ref mut object) if object.contains_key(key) => Rc::make_mut(object).get_mut(key),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow the element of an array at the given index, first copying the array if
+    /// it is shared.
+    pub fn get_index_mut(&mut self, index: usize) -> Option<&mut RcValue> {
+        match *self {
+            RcValue::Array(ref mut array) if index < array.len() => Rc::make_mut(array).get_mut(index),
+            _ => None,
+        }
+    }
+
+    /// Borrow the value addressed by a JSON Pointer, such as `/a/0/b`.
+    ///
+    /// Returns None if the pointer is malformed or any segment is missing.
+    pub fn pointer(&self, pointer: &str) -> Option<&RcValue> {
+        let mut value = self;
+        for segment in pointer_segments(pointer)? {
+            value = match *value {
+                RcValue::Object(ref object) => object.get(&*segment)?,
+                RcValue::Array(ref array) => array.get(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Mutably borrow the value addressed by a JSON Pointer, first copying each shared object
+    /// and array along the way.
+    ///
+    /// Returns None if the pointer is malformed or any segment is missing, without copying
+    /// anything.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut RcValue> {
+        self.pointer(pointer)?;
+        let mut value = self;
+        for segment in pointer_segments(pointer)? {
+            value = match *value {
+                RcValue::Object(ref mut object) => Rc::make_mut(object).get_mut(&*segment)?,
+                RcValue::Array(ref mut array) => Rc::make_mut(array).get_mut(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Convert this value to a `Value`, copying whatever it shares with other values.
+    pub fn into_owned(self) -> Value {
+        match self {
+            RcValue::String(string) => Value::String(String::from(&*string)),
+            RcValue::Number(number) => Value::Number(number),
+            RcValue::Integer(integer) => Value::Integer(integer),
+            RcValue::RawNumber(number) => Value::RawNumber(String::from(&*number)),
+            RcValue::Bool(bool_) => Value::Bool(bool_),
+            RcValue::Null => Value::Null,
+            RcValue::Object(object) => {
+                let object = Rc::try_unwrap(object).unwrap_or_else(|object| (*object).clone());
+                Value::Object(object.into_iter().map(|(key, value)| (key, value.into_owned())).collect())
+            }
+            RcValue::Array(array) => {
+                let array = Rc::try_unwrap(array).unwrap_or_else(|array| (*array).clone());
+                Value::Array(array.into_iter().map(RcValue::into_owned).collect())
+            }
+        }
+    }
+}
+
+impl From<Value> for RcValue {
+    fn from(value: Value) -> RcValue {
+        match value {
+            Value::String(string) => RcValue::String(Rc::from(string)),
+            Value::Number(number) => RcValue::Number(number),
+            Value::Integer(integer) => RcValue::Integer(integer),
+            Value::RawNumber(number) => RcValue::RawNumber(Rc::from(number)),
+            Value::Bool(bool_) => RcValue::Bool(bool_),
+            Value::Null => RcValue::Null,
+            Value::Object(object) => {
+                let object = object.into_iter().map(|(key, value)| (key, RcValue::from(value))).collect();
+                RcValue::Object(Rc::new(object))
+            }
+            Value::Array(array) => RcValue::Array(Rc::new(array.into_iter().map(RcValue::from).collect())),
+        }
+    }
+}
+
+/// The type of a JSON value, without its contents.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ValueKind {
+    String,
+    /// Each of `Value::Number`, `Value::Integer`, and `Value::RawNumber`.
+    Number,
+    Bool,
+    Null,
+    Object,
+    Array,
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ValueKind::String => "string",
+            ValueKind::Number => "number",
+            ValueKind::Bool => "boolean",
+            ValueKind::Null => "null",
+            ValueKind::Object => "object",
+            ValueKind::Array => "array",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A value of the wrong type.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeError {
+    pub expected: ValueKind,
+    pub actual: ValueKind,
+    /// A description of the value supplied by the caller, such as a field name.
+    pub context: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} for {}, found {}", self.expected, self.context, self.actual)
+    }
+}
+
+impl error::Error for TypeError {}
+
+/// An error from parsing a value with `str::parse`.
+///
+/// `FromStr` cannot return an error that borrows its input, so this holds a copy of the
+/// `ParseError`'s token rather than the error itself.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FromStrError {
+    pub kind: ParseErrorKind,
+    /// The reason the lexer rejected the token, as in `ParseError::lex_error`.
+    pub lex_error: Option<LexErrorKind>,
+    /// The source text of the token where parsing failed.
+    pub span: String,
+}
+
+impl fmt::Display for FromStrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&parse::describe(self.kind, self.lex_error, &self.span))
+    }
+}
+
+impl error::Error for FromStrError {}
+
+/// How `Value::merge` resolves a key present in both objects, when the values are not both
+/// objects.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MergePolicy {
+    /// The merged value replaces the existing one.
+    Replace,
+    /// The merged value replaces the existing one, except that arrays are concatenated.
+    Concat,
+    /// The existing value is kept, so merging only adds missing keys.
+    Keep,
+}
+
+/// An error from `Value::unflatten`, for a key that is both a leaf and a prefix of other keys.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnflattenError {
+    pub key: String,
+}
+
+impl fmt::Display for UnflattenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key {:?} conflicts with another key", self.key)
+    }
+}
+
+impl error::Error for UnflattenError {}
+
+/// An error from `Value::require_key`, for an object without the key.
+///
+/// A value does not know where it is in a document, so this only names the key. Chained
+/// lookups such as `value.require_key("user")?.require_key("id")?` report the innermost one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MissingKeyError {
+    pub key: String,
+}
+
+impl fmt::Display for MissingKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "missing key {:?}", self.key)
+    }
+}
+
+impl error::Error for MissingKeyError {}
+
+impl Value {
+    /// Start building an object value.
+    pub fn object() -> ObjectBuilder {
+        ObjectBuilder { object: Object::new() }
+    }
+
+    /// Start building an array value.
+    pub fn array() -> ArrayBuilder {
+        ArrayBuilder { array: Array::new() }
+    }
+
+    /// The type of this value.
+    pub fn kind(&self) -> ValueKind {
+        match *self {
+            Value::String(_) => ValueKind::String,
+            Value::Number(_) | Value::Integer(_) | Value::RawNumber(_) => ValueKind::Number,
+            Value::Bool(_) => ValueKind::Bool,
+            Value::Null => ValueKind::Null,
+            Value::Object(_) => ValueKind::Object,
+            Value::Array(_) => ValueKind::Array,
+        }
+    }
+
+    /// The number of members in an object or elements in an array, or `None` for other values.
+    pub fn len(&self) -> Option<usize> {
+        match *self {
+            Value::Object(ref object) => Some(object.len()),
+            Value::Array(ref array) => Some(array.len()),
+            _ => None,
+        }
+    }
+
+    /// Whether an object or array is empty, or `None` for other values.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// The members of an object sorted by key, or `None` for other values.
+    pub fn sorted_entries(&self) -> Option<Vec<(&String, &Value)>> {
+        match *self {
+            Value::Object(ref object) => {
+                let mut entries: Vec<_> = object.iter().collect();
+                entries.sort_by_key(|&(key, _)| key);
+                Some(entries)
+            }
+            _ => None,
+        }
+    }
+
+    /// Estimate the memory this value occupies in bytes, including everything it owns.
+    ///
+    /// This counts the capacity of each string, array and object, but not allocator overhead
+    /// or the unused control bytes of an object's table.
+    pub fn deep_size(&self) -> usize {
+        mem::size_of::<Value>() + self.heap_size()
+    }
+
+    fn heap_size(&self) -> usize {
+        match *self {
+            Value::String(ref string) | Value::RawNumber(ref string) => string.capacity(),
+            Value::Object(ref object) => {
+                let members = object.iter()
+                    .map(|(key, value)| key.capacity() + value.heap_size())
+                    .sum::<usize>();
+                object.capacity() * mem::size_of::<(String, Value)>() + members
+            }
+            Value::Array(ref array) => {
+                let elements = array.iter().map(Value::heap_size).sum::<usize>();
+                array.capacity() * mem::size_of::<Value>() + elements
+            }
+            _ => 0,
+        }
+    }
+
+    /// Take this value, leaving `Null` in its place.
+    pub fn take(&mut self) -> Value {
+        mem::replace(self, Value::Null)
+    }
+
+    /// Whether this is an object with a member with the given key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        match *self {
+            Value::Object(ref object) => object.contains_key(key),
+            _ => false,
+        }
+    }
+
+    /// Mutably borrow the member of an object with the given key.
+    ///
+    /// Returns None if this is not an object or it has no such member.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match *self {
+            Value::Object(ref mut object) => object.get_mut(key),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow the element of an array at the given index.
+    ///
+    /// Returns None if this is not an array or the index is out of bounds.
+    pub fn get_index_mut(&mut self, index: usize) -> Option<&mut Value> {
+        match *self {
+            Value::Array(ref mut array) => array.get_mut(index),
+            _ => None,
+        }
+    }
+
+    /// Borrow this value's members, if it is an object.
+    pub fn as_object(&self) -> Option<&Object> {
+        match *self {
+            Value::Object(ref object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow this value's members, if it is an object.
+    pub fn as_object_mut(&mut self) -> Option<&mut Object> {
+        match *self {
+            Value::Object(ref mut object) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Borrow this value's elements, if it is an array.
+    pub fn as_array(&self) -> Option<&Array> {
+        match *self {
+            Value::Array(ref array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow this value's elements, if it is an array.
+    pub fn as_array_mut(&mut self) -> Option<&mut Array> {
+        match *self {
+            Value::Array(ref mut array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Borrow the value addressed by a JSON Pointer, such as `/a/0/b`.
+    ///
+    /// Returns None if the pointer is malformed or any segment is missing.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value> {
+        let mut value = self;
+        for segment in pointer_segments(pointer)? {
+            value = match *value {
+                Value::Object(ref object) => object.get(&*segment)?,
+                Value::Array(ref array) => array.get(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Whether a JSON Pointer, such as `/a/0/b`, is well-formed and addresses a value.
+    pub fn has_pointer(&self, pointer: &str) -> bool {
+        self.pointer(pointer).is_some()
+    }
+
+    /// Mutably borrow the value addressed by a JSON Pointer, such as `/a/0/b`.
+    ///
+    /// Returns None if the pointer is malformed or any segment is missing.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value> {
+        let mut value = self;
+        for segment in pointer_segments(pointer)? {
+            value = match *value {
+                Value::Object(ref mut object) => object.get_mut(&*segment)?,
+                Value::Array(ref mut array) => array.get_mut(pointer_index(&segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Borrow a nested value by following object keys and array indices in turn, so
+    /// `value.get_path(&["a", "0"])` is the first element of the array under `"a"`.
+    ///
+    /// Unlike `pointer`, the segments are not escaped. Returns None if any segment is missing.
+    pub fn get_path(&self, segments: &[&str]) -> Option<&Value> {
+        let mut value = self;
+        for segment in segments {
+            value = match *value {
+                Value::Object(ref object) => object.get(*segment)?,
+                Value::Array(ref array) => array.get(pointer_index(segment)?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+
+    /// Get the entry for a key in an object, for in-place insertion or modification.
+    ///
+    /// Returns None if this is not an object.
+    pub fn entry<'a, K: Into<String>>(&'a mut self, key: K) -> Option<Entry<'a, String, Value>> {
+        match *self {
+            Value::Object(ref mut object) => Some(object.entry(key.into())),
+            _ => None,
+        }
+    }
+
+    /// Mutably borrow the member of an object with the given key, first inserting the result of
+    /// `f` if there is no such member.
+    ///
+    /// Returns None if this is not an object, without calling `f`.
+    pub fn get_or_insert_with<F: FnOnce() -> Value>(&mut self, key: &str, f: F) -> Option<&mut Value> {
+        match *self {
+            Value::Object(ref mut object) => Some(object.entry(String::from(key)).or_insert_with(f)),
+            _ => None,
+        }
+    }
+
+    /// Call `f` on this value and then on each value it contains, depth-first.
+    ///
+    /// Object members are visited in an arbitrary order.
+    pub fn visit<F: FnMut(&Value)>(&self, f: &mut F) {
+        f(self);
+        match *self {
+            Value::Object(ref object) => for value in object.values() { value.visit(f) },
+            Value::Array(ref array) => for value in array { value.visit(f) },
+            _ => {}
+        }
+    }
+
+    /// Call `f` on this value and then on each value it contains, depth-first, allowing `f` to
+    /// modify them.
+    ///
+    /// The values contained by a node are visited after `f` returns, so if it replaces a node,
+    /// the replacement's contents are visited instead. Object members are visited in an
+    /// arbitrary order.
+    pub fn visit_mut<F: FnMut(&mut Value)>(&mut self, f: &mut F) {
+        f(self);
+        match *self {
+            Value::Object(ref mut object) => for value in object.values_mut() { value.visit_mut(f) },
+            Value::Array(ref mut array) => for value in array { value.visit_mut(f) },
+            _ => {}
+        }
+    }
+
+    /// Call `f` on every string value in this value, including this value itself, allowing `f`
+    /// to modify them in place.
+    ///
+    /// Object keys are not passed to `f`, since changing them could make two keys collide.
+    pub fn map_strings<F: FnMut(&mut String)>(&mut self, mut f: F) {
+        self.visit_mut(&mut |value| if let Value::String(ref mut string) = *value {
+            f(string);
+        });
+    }
+
+    /// Convert every `Number` with an integral value that fits in an `i64` into an `Integer`,
+    /// including this value itself, so `3.0` and `3` have the same representation.
+    ///
+    /// Negative zero becomes `Integer(0)`. Raw numbers, fractions and non-finite numbers are left
+    /// unchanged.
+    pub fn normalize_numbers(&mut self) {
+        // 2^63, the bounds of an `i64`, which is exactly representable as an `f64`.
+        const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+        self.visit_mut(&mut |value| if let Value::Number(number) = *value {
+            if number.fract() == 0.0 && (-LIMIT..LIMIT).contains(&number) {
+                *value = Value::Integer(number as i64);
+            }
+        });
+    }
+
+    /// Flatten nested objects and arrays into an object of their scalar leaves, keyed by their
+    /// paths joined with `.`, so `{"a":{"b":1},"c":[true]}` becomes `{"a.b":1,"c.0":true}`.
+    pub fn flatten(&self) -> Object {
+        self.flatten_with('.')
+    }
+
+    /// Flatten nested objects and arrays, joining paths with the given separator.
+    ///
+    /// Empty objects and arrays have no leaves, so they do not appear in the result. A scalar
+    /// that is not in any container has the empty key.
+    pub fn flatten_with(&self, separator: char) -> Object {
+        let mut flat = Object::new();
+        self.flatten_into(&mut String::new(), true, separator, &mut flat);
+        flat
+    }
+
+    /// Flatten this value into `flat` under `path`. The root has no separator before its keys,
+    /// even though an empty key has the same empty path.
+    fn flatten_into(&self, path: &mut String, root: bool, separator: char, flat: &mut Object) {
+        let len = path.len();
+        let push = |path: &mut String, segment: &str| {
+            if !root {
+                path.push(separator);
+            }
+            path.push_str(segment);
+        };
+        match *self {
+            Value::Object(ref object) => for (key, value) in object {
+                push(path, key);
+                value.flatten_into(path, false, separator, flat);
+                path.truncate(len);
+            },
+            Value::Array(ref array) => for (index, value) in array.iter().enumerate() {
+                push(path, &index.to_string());
+                value.flatten_into(path, false, separator, flat);
+                path.truncate(len);
+            },
+            ref value => { flat.insert(path.clone(), value.clone()); }
+        }
+    }
+
+    /// Iterate over the scalar leaves of this value, each with its JSON Pointer, such as
+    /// `("/a/0/b", &scalar)`.
+    ///
+    /// Unlike `flatten`, this borrows the leaves and escapes keys as RFC 6901 requires. Empty
+    /// objects and arrays have no leaves, and a scalar that is not in any container has the
+    /// empty pointer. Array elements are produced in order, and object members in an arbitrary
+    /// order.
+    pub fn leaves<'a>(&'a self) -> Leaves<'a> {
+        Leaves { stack: vec![(String::new(), self)] }
+    }
+
+    /// Merge `other` into this value, recursively combining objects key by key.
+    ///
+    /// Members of `other` whose keys are missing here are added. Where both values are objects
+    /// they are merged in turn, and any other conflict is resolved by `policy`. Unlike a JSON
+    /// merge patch, `null` is merged like any other value, rather than removing the key.
+    pub fn merge(&mut self, other: Value, policy: MergePolicy) {
+        match (self, other) {
+            (Value::Object(object), Value::Object(other)) => {
+                for (key, value) in other {
+                    match object.get_mut(&key) {
+                        Some(existing) => existing.merge(value, policy),
+                        None => { object.insert(key, value); }
+                    }
+                }
+            }
+            (Value::Array(array), Value::Array(other)) if policy == MergePolicy::Concat => {
+                array.extend(other);
+            }
+            (_, _) if policy == MergePolicy::Keep => {}
+            (this, other) => *this = other,
+        }
+    }
+
+    /// Rebuild nested objects and arrays from the leaves produced by `flatten_with`.
+    ///
+    /// Each container becomes an array if its keys are exactly the indices `0`, `1`, and so on,
+    /// and an object otherwise. Flattening drops empty containers, so they are not rebuilt.
+    pub fn unflatten(flat: &Object, separator: char) -> Result<Value, UnflattenError> {
+        if let Some(value) = flat.get("") {
+            if flat.len() > 1 {
+                return Err(UnflattenError { key: String::new() });
+            }
+            return Ok(value.clone());
+        }
+
+        let mut root = Unflattened::Branch(HashMap::new());
+        for (key, value) in flat {
+            let conflict = || UnflattenError { key: key.clone() };
+            let mut node = &mut root;
+            for segment in key.split(separator) {
+                node = match *node {
+                    Unflattened::Branch(ref mut branch) => branch
+                        .entry(String::from(segment))
+                        .or_insert_with(|| Unflattened::Branch(HashMap::new())),
+                    Unflattened::Leaf(_) => return Err(conflict()),
+                };
+            }
+            match *node {
+                Unflattened::Branch(ref branch) if branch.is_empty() => {}
+                _ => return Err(conflict()),
+            }
+            *node = Unflattened::Leaf(value.clone());
+        }
+        Ok(root.into_value())
+    }
+
+    /// Borrow this value as a string, or produce a `TypeError` that mentions `context`.
+    pub fn as_str_or(&self, context: &str) -> Result<&str, TypeError> {
+        match *self {
+            Value::String(ref string) => Ok(string),
+            _ => Err(self.type_error(ValueKind::String, context)),
+        }
+    }
+
+    /// Check that this is an object with every one of `keys`, or produce the keys it is missing.
+    ///
+    /// A value that is not an object is missing every key.
+    pub fn require(&self, keys: &[&str]) -> Result<(), Vec<String>> {
+        let missing: Vec<_> = keys.iter()
+            .filter(|key| !self.contains_key(key))
+            .map(|key| String::from(*key))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Borrow the member of an object with the given key, or produce an error naming it.
+    ///
+    /// A value that is not an object is missing every key.
+    pub fn require_key(&self, key: &str) -> Result<&Value, MissingKeyError> {
+        let member = match *self {
+            Value::Object(ref object) => object.get(key),
+            _ => None,
+        };
+        member.ok_or_else(|| MissingKeyError { key: String::from(key) })
+    }
+
+    /// Keep only the members of an object for which `f(Some(key), value)` returns true, or the
+    /// elements of an array for which `f(None, value)` returns true.
+    ///
+    /// Other values are left unchanged.
+    pub fn retain<F: FnMut(Option<&str>, &Value) -> bool>(&mut self, mut f: F) {
+        match *self {
+            Value::Object(ref mut object) => object.retain(|key, value| f(Some(key), value)),
+            Value::Array(ref mut array) => array.retain(|value| f(None, value)),
+            _ => {}
+        }
+    }
+
+    /// Copy the members of an object whose keys match `pred` into a new object.
+    ///
+    /// A value that is not an object produces `Null`.
+    pub fn filter_keys<F: Fn(&str) -> bool>(&self, pred: F) -> Value {
+        match *self {
+            Value::Object(ref object) => Value::Object(object.iter()
+                .filter(|&(key, _)| pred(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()),
+            _ => Value::Null,
+        }
+    }
+
+    /// Copy the members of an object into two new objects, the first with the keys that match
+    /// `pred` and the second with the rest.
+    ///
+    /// A value that is not an object produces two `Null`s, as with `filter_keys`.
+    pub fn partition<F: Fn(&str) -> bool>(&self, pred: F) -> (Value, Value) {
+        match *self {
+            Value::Object(ref object) => {
+                let (matching, rest): (Object, Object) = object.iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .partition(|(key, _)| pred(key));
+                (Value::Object(matching), Value::Object(rest))
+            }
+            _ => (Value::Null, Value::Null),
+        }
+    }
+
+    fn type_error(&self, expected: ValueKind, context: &str) -> TypeError {
+        TypeError { expected, actual: self.kind(), context: String::from(context) }
+    }
+
+    /// Convert this value to the given kind, for loosely typed input.
+    ///
+    /// The supported coercions are:
+    ///
+    /// * A value of the requested kind is returned unchanged.
+    /// * A string holding a JSON number, such as `"12"` or `" -1.5e3"`, becomes that number.
+    /// * The string `"true"` or `"false"` becomes that boolean.
+    /// * A number or boolean becomes a string of its JSON representation, so `12` becomes
+    ///   `"12"`. NaN and the infinities have no JSON representation, so they are not coerced.
+    ///
+    /// Every other coercion returns None.
+    pub fn coerce_to(&self, kind: ValueKind) -> Option<Value> {
+        if self.kind() == kind {
+            return Some(self.clone());
+        }
+        match (self, kind) {
+            (Value::String(string), ValueKind::Number) => match string.parse::<Value>() {
+                Ok(number) if number.kind() == ValueKind::Number => Some(number),
+                _ => None,
+            },
+            (Value::String(string), ValueKind::Bool) => match &string[..] {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            (Value::Number(number), ValueKind::String) if !number.is_finite() => None,
+            (Value::Number(_), ValueKind::String)
+            | (Value::Integer(_), ValueKind::String)
+            | (Value::RawNumber(_), ValueKind::String)
+            | (Value::Bool(_), ValueKind::String) => Some(Value::String(self.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Whether this value counts as true in a conditional, loosely following JavaScript.
+    ///
+    /// `null`, `false`, the empty string, zero and NaN are falsy, in any number representation.
+    /// Unlike JavaScript, empty objects and arrays are also falsy. Everything else is truthy,
+    /// including the strings `"0"` and `"false"`.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::String(ref string) => !string.is_empty(),
+            Value::Number(_) | Value::Integer(_) | Value::RawNumber(_) => match self.as_f64_lossy() {
+                Some(number) => number != 0.0 && !number.is_nan(),
+                None => true,
+            },
+            Value::Bool(bool_) => bool_,
+            Value::Null => false,
+            Value::Object(ref object) => !object.is_empty(),
+            Value::Array(ref array) => !array.is_empty(),
+        }
+    }
+
+    /// Compare two values, treating numbers as equal when they have the same numeric value.
+    ///
+    /// Unlike `==`, this ignores how a number is represented, so `Number(1.0)` is equal to
+    /// `Integer(1)` and `RawNumber("1")`, and `RawNumber("1.0")` is equal to `RawNumber("10e-1")`.
+    /// Objects are compared regardless of key order, and arrays element by element.
+    pub fn numeric_eq(&self, other: &Value) -> bool {
+        self.eq_with(other, &mut |a, b| match (a, b) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            _ => match (a.as_f64_lossy(), b.as_f64_lossy()) {
+                (Some(x), Some(y)) => x == y,
+                _ => a == b,
+            },
+        })
+    }
+
+    /// Compare two values, treating numbers as equal when they differ by at most `epsilon`.
+    ///
+    /// Numbers of any representation are compared as `f64`s. Everything else is compared
+    /// exactly, with objects compared regardless of key order and arrays element by element.
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        self.eq_with(other, &mut |a, b| match (a.as_f64_lossy(), b.as_f64_lossy()) {
+            (Some(x), Some(y)) => x == y || (x - y).abs() <= epsilon,
+            _ => a == b,
+        })
+    }
+
+    /// Compare two values, treating objects as sets of members regardless of key order, and
+    /// arrays element by element.
+    ///
+    /// This is the same as `==` while `Object` is unordered, but does not depend on it.
+    pub fn structurally_eq(&self, other: &Value) -> bool {
+        self.eq_with(other, &mut |a, b| a == b)
+    }
+
+    /// Compare two values member by member and element by element, comparing everything else
+    /// with `leaf`.
+    fn eq_with(&self, other: &Value, leaf: &mut dyn FnMut(&Value, &Value) -> bool) -> bool {
+        match (self, other) {
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len() && a.iter().all(|(key, a)| match b.get(key) {
+                    Some(b) => a.eq_with(b, leaf),
+                    None => false,
+                })
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_with(b, leaf))
+            }
+            _ => leaf(self, other),
+        }
+    }
+
+    /// Convert any number representation to an `f64`, possibly losing precision for large
+    /// integers and long raw numbers.
+    ///
+    /// Returns None if this is not a number.
+    pub fn as_f64_lossy(&self) -> Option<f64> {
+        match *self {
+            Value::Number(number) => Some(number),
+            Value::Integer(integer) => Some(integer as f64),
+            Value::RawNumber(ref number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Split a JSON Pointer into its unescaped segments.
+fn pointer_segments(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    Some(pointer[1..].split('/').map(|segment| segment.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+/// Escape a key or index as a JSON Pointer segment.
+pub(crate) fn escape_segment(segment: &str) -> String {
+    segment.replace("~", "~0").replace("/", "~1")
+}
+
+/// Parse a JSON Pointer segment as an array index, which has no sign or leading zeros.
+fn pointer_index(segment: &str) -> Option<usize> {
+    if !segment.bytes().all(|b| b.is_ascii_digit()) || (segment.len() > 1 && segment.starts_with('0')) {
+        return None;
+    }
+    segment.parse().ok()
+}
+
+/// A partially rebuilt value in `Value::unflatten`.
+enum Unflattened {
+    Leaf(Value),
+    Branch(HashMap<String, Unflattened>),
+}
+
+impl Unflattened {
+    fn into_value(self) -> Value {
+        match self {
+            Unflattened::Leaf(value) => value,
+            Unflattened::Branch(mut branch) => {
+                let len = branch.len();
+                if len > 0 && (0..len).all(|index| branch.contains_key(&index.to_string())) {
+                    let array = (0..len).map(|index| branch.remove(&index.to_string()).unwrap());
+                    Value::Array(array.map(Unflattened::into_value).collect())
+                } else {
+                    Value::Object(branch.into_iter().map(|(key, node)| (key, node.into_value())).collect())
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the scalar leaves of a value and their JSON Pointers, created by
+/// `Value::leaves`.
+pub struct Leaves<'a> {
+    /// The values left to visit, with the last one next.
+    stack: Vec<(String, &'a Value)>,
+}
+
+impl<'a> Iterator for Leaves<'a> {
+    type Item = (String, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((pointer, value)) = self.stack.pop() {
+            match *value {
+                Value::Object(ref object) => {
+                    let members = object.iter()
+                        .map(|(key, value)| (format!("{}/{}", pointer, escape_segment(key)), value));
+                    self.stack.extend(members);
+                }
+                Value::Array(ref array) => {
+                    let elements = array.iter().enumerate()
+                        .map(|(index, value)| (format!("{}/{}", pointer, index), value));
+                    self.stack.extend(elements.rev());
+                }
+                _ => return Some((pointer, value)),
+            }
+        }
+        None
+    }
+}
+
+/// A builder for object values, created by `Value::object`.
+pub struct ObjectBuilder {
+    object: Object,
+}
+
+impl ObjectBuilder {
+    /// Add a key-value pair to the object, replacing any previous value for the key.
+    pub fn insert<K: Into<String>>(mut self, key: K, value: Value) -> Self {
+        self.object.insert(key.into(), value);
+        self
+    }
+
+    /// Finish the object.
+    pub fn build(self) -> Value {
+        Value::Object(self.object)
+    }
+}
+
+/// A builder for array values, created by `Value::array`.
+pub struct ArrayBuilder {
+    array: Array,
+}
+
+impl ArrayBuilder {
+    /// Add an element to the end of the array.
+    pub fn push(mut self, value: Value) -> Self {
+        self.array.push(value);
+        self
+    }
+
+    /// Finish the array.
+    pub fn build(self) -> Value {
+        Value::Array(self.array)
+    }
+}
+
+/// Options for serializing values with `Value::write_with`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct WriteOptions {
+    /// Write object members in order by key, rather than in the object's arbitrary order.
+    ///
+    /// Combined with `format`, this produces stable output suitable for diffing.
+    pub sort_keys: bool,
+    /// Escape every non-ASCII character in strings as `\uXXXX`, using surrogate pairs for
+    /// characters outside the Basic Multilingual Plane, rather than writing it as UTF-8.
+    pub ascii_only: bool,
+    /// Escape `/` in strings as `\/`, so that JSON embedded in HTML cannot contain `</script>`.
+    pub escape_slash: bool,
+    /// Omit object members whose value is `null`. Array elements are always written, so their
+    /// indices are preserved.
+    pub skip_nulls: bool,
+    /// Round `Number`s before writing them, rather than writing the shortest representation
+    /// that parses back to the same value. Integers and raw numbers are written unchanged.
+    pub precision: Option<Precision>,
+    /// Write NaN and infinite `Number`s as the JavaScript literals `NaN`, `Infinity` and
+    /// `-Infinity`, rather than as `null`. This is not valid JSON, and this crate's parser does
+    /// not accept it either, so it is only for consumers that do.
+    pub non_finite_literals: bool,
+}
+
+/// How `WriteOptions::precision` rounds numbers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Precision {
+    /// Round to this many significant digits, and write the shortest representation of that.
+    Significant(usize),
+    /// Round to this many digits after the decimal point, and write exactly that many, so
+    /// `Decimals(2)` writes `1.5` as `1.50`.
+    Decimals(usize),
+}
+
+/// A NaN or infinite number, which JSON cannot represent, from `Value::try_to_string_with`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NonFiniteError {
+    pub number: f64,
+}
+
+impl fmt::Display for NonFiniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} has no JSON representation", self.number)
+    }
+}
+
+impl error::Error for NonFiniteError {}
+
+/// Why `Value::write` stopped.
+enum WriteError {
+    Fmt(fmt::Error),
+    NonFinite(f64),
+}
+
+impl From<fmt::Error> for WriteError {
+    fn from(error: fmt::Error) -> WriteError {
+        WriteError::Fmt(error)
+    }
+}
+
+impl From<WriteError> for fmt::Error {
+    fn from(error: WriteError) -> fmt::Error {
+        match error {
+            WriteError::Fmt(error) => error,
+            WriteError::NonFinite(_) => fmt::Error,
+        }
+    }
+}
+
+impl Value {
+    /// Serialize this value as compact JSON, with the given options.
+    ///
+    /// Non-finite numbers have no JSON representation. They are written as `null` unless
+    /// `options.non_finite_literals` is set, because a `fmt::Write` cannot report why it failed;
+    /// `try_to_string_with` rejects them instead.
+    pub fn write_with<W: fmt::Write>(&self, out: &mut W, options: WriteOptions) -> fmt::Result {
+        Ok(self.write(out, None, options, false)?)
+    }
+
+    /// Serialize this value as compact JSON, with the given options, and with each comment
+    /// from `Parse::value_with_comments` before the value or object member it belongs to.
+    ///
+    /// Line comments are followed by a newline, so the output can be parsed again with
+    /// `Options::allow_comments`. Comments for values that no longer exist are not written.
+    pub fn write_with_comments<W: fmt::Write>(&self, out: &mut W, comments: &Comments, options: WriteOptions) -> fmt::Result {
+        Ok(self.write(out, Some(comments), options, false)?)
+    }
+
+    /// Write this value, or with `reject_non_finite`, stop at the first non-finite number unless
+    /// `options.non_finite_literals` is set.
+    fn write<W: fmt::Write>(&self, out: &mut W, comments: Option<&Comments>, options: WriteOptions, reject_non_finite: bool) -> Result<(), WriteError> {
+        // The JSON Pointer to a member or element, which is only needed to look up comments.
+        let member = |pointer: &str, key: &str| match comments {
+            Some(_) => format!("{}/{}", pointer, escape_segment(key)),
+            None => String::new(),
+        };
+
+        // Keep the work left to do on an explicit stack, so deeply nested values cannot overflow
+        // the call stack.
+        let mut stack = vec![Write::Value(self, String::new())];
+        if let Some(root) = comments.and_then(|comments| comments.get("")) {
+            stack.push(Write::Comments(root));
+        }
+        while let Some(write) = stack.pop() {
+            let (value, pointer) = match write {
+                Write::Value(value, pointer) => (value, pointer),
+                Write::Key(key) => {
+                    write_string(out, key, options)?;
+                    out.write_str(":")?;
+                    continue;
+                }
+                Write::Str(string) => {
+                    out.write_str(string)?;
+                    continue;
+                }
+                Write::Comments(comments) => {
+                    for comment in comments {
+                        out.write_str(comment)?;
+                        if comment.starts_with("//") {
+                            out.write_str("\n")?;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match *value {
+                Value::String(ref string) => write_string(out, string, options)?,
+                Value::Number(number) if number.is_finite() => match options.precision {
+                    None => write!(out, "{}", number)?,
+                    Some(Precision::Significant(digits)) => {
+                        let rounded: f64 = format!("{:.*e}", digits.max(1) - 1, number).parse().unwrap();
+                        write!(out, "{}", rounded)?
+                    }
+                    Some(Precision::Decimals(digits)) => write!(out, "{:.*}", digits, number)?,
+                },
+                Value::Number(number) if options.non_finite_literals => match number {
+                    _ if number.is_nan() => out.write_str("NaN")?,
+                    _ if number > 0.0 => out.write_str("Infinity")?,
+                    _ => out.write_str("-Infinity")?,
+                },
+                Value::Number(number) if reject_non_finite => return Err(WriteError::NonFinite(number)),
+                Value::Number(_) => out.write_str("null")?,
+                Value::Integer(integer) => write!(out, "{}", integer)?,
+                Value::RawNumber(ref number) => out.write_str(number)?,
+                Value::Bool(bool_) => write!(out, "{}", bool_)?,
+                Value::Null => out.write_str("null")?,
+                Value::Object(ref object) => {
+                    let mut members: Vec<_> = object.iter()
+                        .filter(|&(_, value)| !(options.skip_nulls && *value == Value::Null))
+                        .collect();
+                    if options.sort_keys {
+                        members.sort_by_key(|&(key, _)| key);
+                    }
+
+                    // Push the members in reverse, so they are popped in order.
+                    out.write_str("{")?;
+                    stack.push(Write::Str("}"));
+                    for (i, (key, value)) in members.into_iter().enumerate().rev() {
+                        let pointer = member(&pointer, key);
+                        let member_comments = comments.and_then(|comments| comments.get(&pointer));
+                        stack.push(Write::Value(value, pointer));
+                        stack.push(Write::Key(key));
+                        if let Some(member_comments) = member_comments {
+                            stack.push(Write::Comments(member_comments));
+                        }
+                        if i > 0 {
+                            stack.push(Write::Str(","));
+                        }
+                    }
+                }
+                Value::Array(ref array) => {
+                    out.write_str("[")?;
+                    stack.push(Write::Str("]"));
+                    for (i, value) in array.iter().enumerate().rev() {
+                        let pointer = match comments {
+                            Some(_) => format!("{}/{}", pointer, i),
+                            None => String::new(),
+                        };
+                        let element_comments = comments.and_then(|comments| comments.get(&pointer));
+                        stack.push(Write::Value(value, pointer));
+                        if let Some(element_comments) = element_comments {
+                            stack.push(Write::Comments(element_comments));
+                        }
+                        if i > 0 {
+                            stack.push(Write::Str(","));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Serialize this value as a string of compact JSON, with the given options.
+    pub fn to_string_with(&self, options: WriteOptions) -> String {
+        let mut string = String::new();
+        self.write_with(&mut string, options).expect("writing to a String cannot fail");
+        string
+    }
+
+    /// Serialize this value as a string of compact JSON, with the given options, or fail if it
+    /// has a NaN or infinite number, rather than writing it as `null`.
+    ///
+    /// With `options.non_finite_literals`, such numbers are written as literals instead, and
+    /// this never fails.
+    pub fn try_to_string_with(&self, options: WriteOptions) -> Result<String, NonFiniteError> {
+        let mut string = String::new();
+        match self.write(&mut string, None, options, true) {
+            Ok(()) => Ok(string),
+            Err(WriteError::NonFinite(number)) => Err(NonFiniteError { number }),
+            Err(WriteError::Fmt(_)) => unreachable!("writing to a String cannot fail"),
+        }
+    }
+
+    /// Serialize this value as compact JSON, directly into UTF-8 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_with(&mut Bytes(&mut bytes), WriteOptions::default()).expect("writing to a Vec cannot fail");
+        bytes
+    }
+}
+
+/// A piece of output left to write in `Value::write_with`.
+enum Write<'a> {
+    /// A value, and its JSON Pointer if comments are being written.
+    Value(&'a Value, String),
+    /// An object key and the colon after it.
+    Key(&'a str),
+    Str(&'static str),
+    Comments(&'a [String]),
+}
+
+/// An adapter to write formatted text into a byte buffer.
+struct Bytes<'a>(&'a mut Vec<u8>);
+
+impl<'a> fmt::Write for Bytes<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Serialize a value as compact JSON, with the default `WriteOptions`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_with(f, WriteOptions::default())
+    }
+}
+
+/// Write a string literal, escaping quotes, backslashes, control characters, and with
+/// `options.ascii_only` non-ASCII characters.
+fn write_string<W: fmt::Write>(out: &mut W, string: &str, options: WriteOptions) -> fmt::Result {
+    out.write_str("\"")?;
+    write_escaped(out, string, options)?;
+    out.write_str("\"")
+}
+
+/// Write the contents of a string literal, without its quotes.
+fn write_escaped<W: fmt::Write>(out: &mut W, string: &str, options: WriteOptions) -> fmt::Result {
+    // Write runs of characters that need no escaping all at once.
+    let mut start = 0;
+    for (i, c) in string.char_indices() {
+        let escape = match c {
+            '"' => Some("\\\""),
+            '\\' => Some("\\\\"),
+            '/' if options.escape_slash => Some("\\/"),
+            '\x08' => Some("\\b"),
+            '\x0C' => Some("\\f"),
+            '\n' => Some("\\n"),
+            '\r' => Some("\\r"),
+            '\t' => Some("\\t"),
+            '\x00'..='\x1F' => None,
+            _ if options.ascii_only && !c.is_ascii() => None,
+            _ => continue,
+        };
+
+        out.write_str(&string[start..i])?;
+        match escape {
+            Some(escape) => out.write_str(escape)?,
+            None => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(out, "\\u{:04X}", unit)?;
+                }
+            }
+        }
+        start = i + c.len_utf8();
+    }
+    out.write_str(&string[start..])
+}
+
+/// Escape a string as the contents of a JSON string literal, without the surrounding quotes,
+/// exactly as `Value::write_with` does with the default `WriteOptions`.
+///
+/// Quotes, backslashes and control characters are escaped, and everything else is kept as is.
+/// This is the inverse of `unescape_json_string`.
+pub fn escape_json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    write_escaped(&mut escaped, string, WriteOptions::default()).expect("writing to a String cannot fail");
+    escaped
+}
+
+impl str::FromStr for Value {
+    type Err = FromStrError;
+
+    fn from_str(source: &str) -> Result<Value, FromStrError> {
+        Ok(Parse::new(source).value()?)
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Value {
+    fn eq(&self, other: &&'a str) -> bool {
+        match *self {
+            Value::String(ref string) => string == other,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        match *self {
+            Value::Number(number) => number == *other,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<bool> for Value {
+    fn eq(&self, other: &bool) -> bool {
+        match *self {
+            Value::Bool(bool_) => bool_ == *other,
+            _ => false,
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Value { Value::String(string) }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(string: &'a str) -> Value { Value::String(String::from(string)) }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Value { Value::Number(number) }
+}
+
+impl From<i64> for Value {
+    fn from(integer: i64) -> Value { Value::Integer(integer) }
+}
+
+impl From<bool> for Value {
+    fn from(bool_: bool) -> Value { Value::Bool(bool_) }
+}
+
+impl From<Object> for Value {
+    fn from(object: Object) -> Value { Value::Object(object) }
+}
+
+impl From<Array> for Value {
+    fn from(array: Array) -> Value { Value::Array(array) }
+}
+
+/// Take a value's string, or produce a `TypeError`.
+impl TryFrom<Value> for String {
+    type Error = TypeError;
+
+    fn try_from(value: Value) -> Result<String, TypeError> {
+        match value {
+            Value::String(string) => Ok(string),
+            _ => Err(value.type_error(ValueKind::String, "value")),
+        }
+    }
+}
+
+/// Convert any number representation to an `f64`, or produce a `TypeError`.
+impl TryFrom<Value> for f64 {
+    type Error = TypeError;
+
+    fn try_from(value: Value) -> Result<f64, TypeError> {
+        value.as_f64_lossy().ok_or_else(|| value.type_error(ValueKind::Number, "value"))
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = TypeError;
+
+    fn try_from(value: Value) -> Result<bool, TypeError> {
+        match value {
+            Value::Bool(bool_) => Ok(bool_),
+            _ => Err(value.type_error(ValueKind::Bool, "value")),
+        }
+    }
+}
+
+impl TryFrom<Value> for Array {
+    type Error = TypeError;
+
+    fn try_from(value: Value) -> Result<Array, TypeError> {
+        match value {
+            Value::Array(array) => Ok(array),
+            _ => Err(value.type_error(ValueKind::Array, "value")),
+        }
+    }
+}
+
+impl TryFrom<Value> for Object {
+    type Error = TypeError;
+
+    fn try_from(value: Value) -> Result<Object, TypeError> {
+        match value {
+            Value::Object(object) => Ok(object),
+            _ => Err(value.type_error(ValueKind::Object, "value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error, mem};
+    use std::convert::TryFrom;
+    use std::rc::Rc;
+    use json::{Value, RcValue, ValueKind, Object, Array, TypeError, MissingKeyError, FromStrError};
+    use json::{MergePolicy, WriteOptions, Precision, NonFiniteError};
+    use options::Options;
+    use parse::{Parse, ParseErrorKind, unescape_json_string};
+
+    #[test]
+    fn builders() {
+        let value = Value::object()
+            .insert("ok", true.into())
+            .insert("items", Value::array().push(1.0.into()).push("two".into()).build())
+            .build();
+
+        let mut object = Object::new();
+        object.insert(String::from("ok"), Value::Bool(true));
+        object.insert(String::from("items"), Value::Array(vec![
+            Value::Number(1.0),
+            Value::String(String::from("two")),
+        ]));
+        assert_eq!(value, Value::Object(object));
+    }
+
+    #[test]
+    fn len() {
+        assert_eq!(Value::object().insert("a", 1.0.into()).build().len(), Some(1));
+        assert_eq!(Value::array().build().is_empty(), Some(true));
+        assert_eq!(Value::array().push(Value::Null).build().is_empty(), Some(false));
+        assert_eq!(Value::from("ab").len(), None);
+        assert_eq!(Value::Null.is_empty(), None);
+    }
+
+    #[test]
+    fn sorted_entries() {
+        let value: Value = r#"{"b": 2, "c": 3, "a": 1}"#.parse().unwrap();
+        let entries = value.sorted_entries().unwrap();
+        let keys: Vec<_> = entries.iter().map(|&(key, _)| &key[..]).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+        assert_eq!(entries[0].1, &Value::Number(1.0));
+
+        assert_eq!(Value::array().build().sorted_entries(), None);
+    }
+
+    #[test]
+    fn visit() {
+        let mut value = Value::object()
+            .insert("token", "secret".into())
+            .insert("items", Value::array().push("a".into()).push(1.0.into()).build())
+            .build();
+
+        let mut count = 0;
+        value.visit(&mut |_| count += 1);
+        assert_eq!(count, 5);
+
+        value.visit_mut(&mut |value| if let Value::String(ref mut string) = *value {
+            *string = string.to_uppercase();
+        });
+        assert_eq!(value, Value::object()
+            .insert("token", "SECRET".into())
+            .insert("items", Value::array().push("A".into()).push(1.0.into()).build())
+            .build());
+    }
+
+    #[test]
+    fn map_strings() {
+        let mut value: Value = r#"{" a ": " b ", "c": [" d", 1, {"e": "f "}]}"#.parse().unwrap();
+        value.map_strings(|string| *string = string.trim().to_string());
+        assert_eq!(value, r#"{" a ": "b", "c": ["d", 1, {"e": "f"}]}"#.parse::<Value>().unwrap());
+
+        let mut value = Value::from("x");
+        value.map_strings(|string| string.push('y'));
+        assert_eq!(value, "xy");
+    }
+
+    #[test]
+    fn normalize_numbers() {
+        let mut value: Value = r#"{"a": [3.0, -2, 1.5], "b": -9223372036854775808}"#.parse().unwrap();
+        value.normalize_numbers();
+        assert_eq!(value, Value::object()
+            .insert("a", Value::Array(vec![Value::Integer(3), Value::Integer(-2), Value::Number(1.5)]))
+            .insert("b", Value::Integer(i64::MIN))
+            .build());
+
+        let mut value = Value::Number(9223372036854775808.0);
+        value.normalize_numbers();
+        assert_eq!(value, Value::Number(9223372036854775808.0));
+
+        let mut value = Value::Number(f64::NAN);
+        value.normalize_numbers();
+        assert!(value.as_f64_lossy().unwrap().is_nan());
+    }
+
+    #[test]
+    fn flatten() {
+        let value = Value::object()
+            .insert("a", Value::object().insert("b", 1.0.into()).insert("c", Value::array().build()).build())
+            .insert("d", Value::array().push(true.into()).push(Value::array().push("e".into()).build()).build())
+            .build();
+
+        let flat = Value::object()
+            .insert("a.b", 1.0.into())
+            .insert("d.0", true.into())
+            .insert("d.1.0", "e".into())
+            .build();
+        assert_eq!(Value::Object(value.flatten()), flat);
+
+        assert!(value.flatten_with('/').contains_key("d/1/0"));
+        assert_eq!(Value::Null.flatten().get(""), Some(&Value::Null));
+
+        let value = Value::object()
+            .insert("", Value::object().insert("a", 1.0.into()).build())
+            .insert("a", 2.0.into())
+            .build();
+        let flat = Value::object().insert(".a", 1.0.into()).insert("a", 2.0.into()).build();
+        assert_eq!(Value::Object(value.flatten()), flat);
+        assert_eq!(Value::unflatten(&value.flatten(), '.').unwrap(), value);
+    }
+
+    #[test]
+    fn unflatten() {
+        let value = Value::object()
+            .insert("a", Value::object().insert("b", 1.0.into()).insert("01", 2.0.into()).build())
+            .insert("d", Value::array().push(true.into()).push(Value::array().push("e".into()).build()).build())
+            .build();
+        assert_eq!(Value::unflatten(&value.flatten(), '.').unwrap(), value);
+        assert_eq!(Value::unflatten(&value.flatten_with('/'), '/').unwrap(), value);
+
+        // Keys that are not exactly the indices of an array stay in an object.
+        let mut flat = Object::new();
+        flat.insert(String::from("0"), 1.0.into());
+        flat.insert(String::from("2"), 2.0.into());
+        assert_eq!(Value::unflatten(&flat, '.').unwrap(), Value::Object(flat.clone()));
+
+        let mut flat = Object::new();
+        flat.insert(String::from("a"), Value::Null);
+        flat.insert(String::from("a.b"), Value::Null);
+        let error = Value::unflatten(&flat, '.').unwrap_err();
+        assert!(error.key == "a" || error.key == "a.b");
+
+        assert_eq!(Value::unflatten(&Value::Null.flatten(), '.').unwrap(), Value::Null);
+        assert_eq!(Value::unflatten(&Object::new(), '.').unwrap(), Value::Object(Object::new()));
+    }
+
+    #[test]
+    fn leaves() {
+        let value: Value = r#"{"a": [1, {"b/c": null}, []], "d~": {}}"#.parse().unwrap();
+        let leaves: Vec<_> = value.leaves().collect();
+        assert_eq!(leaves, vec![
+            (String::from("/a/0"), &Value::Number(1.0)),
+            (String::from("/a/1/b~1c"), &Value::Null),
+        ]);
+        for (pointer, leaf) in value.leaves() {
+            assert_eq!(value.pointer(&pointer), Some(leaf));
+        }
+
+        assert_eq!(Value::Bool(true).leaves().collect::<Vec<_>>(), vec![(String::new(), &Value::Bool(true))]);
+    }
+
+    #[test]
+    fn merge() {
+        let base: Value = r#"{ "a": { "b": 1, "c": [1] }, "d": "e" }"#.parse().unwrap();
+        let layer: Value = r#"{ "a": { "c": [2], "f": null }, "d": null, "g": true }"#.parse().unwrap();
+
+        let mut merged = base.clone();
+        merged.merge(layer.clone(), MergePolicy::Replace);
+        let expected: Value = r#"{ "a": { "b": 1, "c": [2], "f": null }, "d": null, "g": true }"#.parse().unwrap();
+        assert_eq!(merged, expected);
+
+        let mut merged = base.clone();
+        merged.merge(layer.clone(), MergePolicy::Concat);
+        let expected: Value = r#"{ "a": { "b": 1, "c": [1, 2], "f": null }, "d": null, "g": true }"#.parse().unwrap();
+        assert_eq!(merged, expected);
+
+        let mut merged = base.clone();
+        merged.merge(layer, MergePolicy::Keep);
+        let expected: Value = r#"{ "a": { "b": 1, "c": [1], "f": null }, "d": "e", "g": true }"#.parse().unwrap();
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn deep_size() {
+        let scalar = mem::size_of::<Value>();
+        assert_eq!(Value::Null.deep_size(), scalar);
+        assert_eq!(Value::String(String::with_capacity(10)).deep_size(), scalar + 10);
+
+        let array = Value::Array(vec![Value::from("abc"), Value::Null]);
+        assert_eq!(array.deep_size(), scalar + 2 * scalar + 3);
+
+        let small: Value = r#"{ "a": [1] }"#.parse().unwrap();
+        let large: Value = r#"{ "a": [1, 2, 3, 4, 5, 6, 7, 8], "b": "a longer string" }"#.parse().unwrap();
+        assert!(small.deep_size() < large.deep_size());
+    }
+
+    #[test]
+    fn take() {
+        let mut value = Value::object()
+            .insert("user", Value::object().insert("password", "hunter2".into()).build())
+            .insert("tags", Value::array().push("a".into()).build())
+            .build();
+
+        let password = value.get_mut("user").and_then(|user| user.get_mut("password")).unwrap();
+        assert_eq!(password.take(), Value::from("hunter2"));
+        *value.get_mut("tags").and_then(|tags| tags.get_index_mut(0)).unwrap() = "b".into();
+
+        let expected = Value::object()
+            .insert("user", Value::object().insert("password", Value::Null).build())
+            .insert("tags", Value::array().push("b".into()).build())
+            .build();
+        assert_eq!(value, expected);
+
+        assert_eq!(value.get_mut("missing"), None);
+        assert_eq!(value.get_index_mut(0), None);
+    }
+
+    #[test]
+    fn pointer() {
+        let mut doc: Value = r#"{ "a": { "b": [null, { "c": 1 }] }, "d/e~f": true, "": 2 }"#.parse().unwrap();
+        assert_eq!(doc.pointer(""), Some(&doc.clone()));
+        assert_eq!(doc.pointer("/a/b/1/c"), Some(&Value::Number(1.0)));
+        assert_eq!(doc.pointer("/d~1e~0f"), Some(&Value::Bool(true)));
+        assert_eq!(doc.pointer("/"), Some(&Value::Number(2.0)));
+        for pointer in &["a", "/x", "/a/b/2", "/a/b/01", "/a/b/+1", "/a/b/0/c"] {
+            assert_eq!(doc.pointer(pointer), None);
+        }
+
+        *doc.pointer_mut("/a/b/1/c").unwrap() = 42.0.into();
+        assert_eq!(doc.pointer("/a/b/1/c"), Some(&Value::Number(42.0)));
+        assert_eq!(doc.pointer_mut("/a/b/1/c/d"), None);
+
+        assert!(doc.has_pointer("/a/b/0"));
+        assert!(!doc.has_pointer("/a/b/2"));
+        assert!(doc.contains_key("d/e~f"));
+        assert!(!doc.contains_key("b"));
+        assert!(!Value::Null.contains_key("a"));
+    }
+
+    #[test]
+    fn get_path() {
+        let config: Value = r#"{ "config": { "db": { "host": "localhost" }, "a/b": [1, 2] } }"#.parse().unwrap();
+        assert_eq!(config.get_path(&["config", "db", "host"]), Some(&Value::from("localhost")));
+        assert_eq!(config.get_path(&["config", "a/b", "1"]), Some(&Value::Number(2.0)));
+        assert_eq!(config.get_path(&[]), Some(&config));
+        assert_eq!(config.get_path(&["config", "a/b", "2"]), None);
+        assert_eq!(config.get_path(&["config", "db", "host", "port"]), None);
+    }
+
+    #[test]
+    fn entry() {
+        let mut counts = Value::object().build();
+        for word in &["a", "b", "a"] {
+            let count = counts.entry(*word).unwrap().or_insert(Value::Integer(0));
+            if let Value::Integer(ref mut count) = *count {
+                *count += 1;
+            }
+        }
+        assert_eq!(counts, Value::object().insert("a", Value::Integer(2)).insert("b", Value::Integer(1)).build());
+        assert!(Value::Null.entry("a").is_none());
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut histogram = Value::object().build();
+        for &(field, bucket) in &[("a", 0), ("b", 1), ("a", 1), ("a", 1)] {
+            let buckets = histogram.get_or_insert_with(field, || Value::array().build()).unwrap();
+            if let Value::Array(ref mut buckets) = *buckets {
+                buckets.resize(buckets.len().max(bucket + 1), Value::Integer(0));
+                if let Value::Integer(ref mut count) = buckets[bucket] {
+                    *count += 1;
+                }
+            }
+        }
+        let mut expected: Value = r#"{"a": [1, 2], "b": [0, 1]}"#.parse().unwrap();
+        expected.normalize_numbers();
+        assert_eq!(histogram, expected);
+
+        let mut value = Value::Null;
+        assert!(value.get_or_insert_with("a", || panic!("called for a non-object")).is_none());
+    }
+
+    #[test]
+    fn rc_value() {
+        let value: Value = r#"{"a": {"b": [1, 2]}, "c": {"d": "e"}}"#.parse().unwrap();
+        let old = RcValue::from(value.clone());
+        let mut new = old.clone();
+        *new.pointer_mut("/a/b/1").unwrap() = RcValue::Bool(true);
+
+        // Only the objects and arrays on the path to the change were copied.
+        match (old.get("c"), new.get("c")) {
+            (Some(RcValue::Object(old)), Some(RcValue::Object(new))) => assert!(Rc::ptr_eq(old, new)),
+            _ => panic!("expected objects"),
+        }
+        assert_eq!(old.clone().into_owned(), value);
+        assert_eq!(new.pointer("/a/b/1"), Some(&RcValue::Bool(true)));
+        assert_eq!(new.into_owned(), r#"{"a": {"b": [1, true]}, "c": {"d": "e"}}"#.parse::<Value>().unwrap());
+
+        let mut new = old.clone();
+        assert_eq!(new.pointer_mut("/a/x"), None);
+        assert_eq!(new.get_index_mut(0), None);
+        match (&old, &new) {
+            (RcValue::Object(old), RcValue::Object(new)) => assert!(Rc::ptr_eq(old, new)),
+            _ => panic!("expected objects"),
+        }
+    }
+
+    #[test]
+    fn as_object_mut() {
+        let mut value: Value = r#"{"a": [1]}"#.parse().unwrap();
+        value.as_object_mut().unwrap().insert(String::from("b"), Value::Null);
+        value.get_mut("a").unwrap().as_array_mut().unwrap().push(true.into());
+        assert_eq!(value, r#"{"a": [1, true], "b": null}"#.parse::<Value>().unwrap());
+        assert_eq!(value.as_object().map(Object::len), Some(2));
+        assert_eq!(value.pointer("/a").and_then(Value::as_array).map(Array::len), Some(2));
+
+        assert!(value.as_array().is_none());
+        assert!(value.get_mut("b").unwrap().as_object_mut().is_none());
+        assert!(value.get_mut("a").unwrap().as_object_mut().is_none());
+        assert!(Value::from("a").as_array_mut().is_none());
+    }
+
+    #[test]
+    fn as_str_or() {
+        assert_eq!(Value::from("a").as_str_or("name"), Ok("a"));
+
+        let error = Value::Number(1.0).as_str_or("field `name`").unwrap_err();
+        assert_eq!(error, TypeError {
+            expected: ValueKind::String,
+            actual: ValueKind::Number,
+            context: String::from("field `name`"),
+        });
+        assert_eq!(error.to_string(), "expected string for field `name`, found number");
+    }
+
+    #[test]
+    fn require() {
+        let body: Value = r#"{ "id": 1, "name": null }"#.parse().unwrap();
+        assert_eq!(body.require(&["id", "name"]), Ok(()));
+        assert_eq!(body.require(&["id", "email", "age"]), Err(vec![String::from("email"), String::from("age")]));
+        assert_eq!(Value::from("id").require(&["id"]), Err(vec![String::from("id")]));
+    }
+
+    #[test]
+    fn require_key() {
+        let value: Value = r#"{"user": {"id": 1}}"#.parse().unwrap();
+        assert_eq!(value.require_key("user").and_then(|user| user.require_key("id")), Ok(&Value::Number(1.0)));
+
+        let error = value.require_key("user").and_then(|user| user.require_key("name")).unwrap_err();
+        assert_eq!(error, MissingKeyError { key: String::from("name") });
+        assert_eq!(error.to_string(), r#"missing key "name""#);
+        assert!(Value::array().build().require_key("user").is_err());
+    }
+
+    #[test]
+    fn retain() {
+        let mut value: Value = r#"{ "name": "a", "blob": "AAAA", "tags": [1, "x", 2] }"#.parse().unwrap();
+        value.retain(|key, _| key != Some("blob"));
+        value.get_mut("tags").unwrap().retain(|_, value| value.kind() == ValueKind::Number);
+        assert_eq!(value, r#"{ "name": "a", "tags": [1, 2] }"#.parse::<Value>().unwrap());
+
+        let mut value = Value::Null;
+        value.retain(|_, _| false);
+        assert_eq!(value, Value::Null);
+    }
+
+    #[test]
+    fn filter_keys() {
+        let response: Value = r#"{ "id": 1, "_rev": 2, "tags": { "_a": 3 } }"#.parse().unwrap();
+        let expected: Value = r#"{ "id": 1, "tags": { "_a": 3 } }"#.parse().unwrap();
+        assert_eq!(response.filter_keys(|key| !key.starts_with('_')), expected);
+        assert_eq!(Value::array().build().filter_keys(|_| true), Value::Null);
+    }
+
+    #[test]
+    fn partition() {
+        let record: Value = r#"{ "id": 1, "_rev": 2, "_meta": { "a": 3 } }"#.parse().unwrap();
+        let (internal, payload) = record.partition(|key| key.starts_with('_'));
+        assert_eq!(internal, r#"{ "_rev": 2, "_meta": { "a": 3 } }"#.parse::<Value>().unwrap());
+        assert_eq!(payload, r#"{ "id": 1 }"#.parse::<Value>().unwrap());
+
+        let (all, none) = record.partition(|_| true);
+        assert_eq!((all, none), (record, Value::object().build()));
+        assert_eq!(Value::from("a").partition(|_| true), (Value::Null, Value::Null));
+    }
+
+    #[test]
+    fn as_f64_lossy() {
+        assert_eq!(Value::Number(1.5).as_f64_lossy(), Some(1.5));
+        assert_eq!(Value::Integer(i64::MAX).as_f64_lossy(), Some(9223372036854775807.0));
+        let raw = Value::RawNumber(String::from("123456789012345678901234567890"));
+        assert_eq!(raw.as_f64_lossy(), Some(1.2345678901234568e29));
+        assert_eq!(Value::from("1").as_f64_lossy(), None);
+    }
+
+    #[test]
+    fn coerce_to() {
+        assert_eq!(Value::from("12").coerce_to(ValueKind::Number), Some(Value::Number(12.0)));
+        assert_eq!(Value::from(" -1.5e3").coerce_to(ValueKind::Number), Some(Value::Number(-1500.0)));
+        assert_eq!(Value::from("false").coerce_to(ValueKind::Bool), Some(Value::Bool(false)));
+        assert_eq!(Value::Number(12.0).coerce_to(ValueKind::String), Some(Value::from("12")));
+        assert_eq!(Value::Integer(-3).coerce_to(ValueKind::String), Some(Value::from("-3")));
+        assert_eq!(Value::Bool(true).coerce_to(ValueKind::String), Some(Value::from("true")));
+        assert_eq!(Value::Null.coerce_to(ValueKind::Null), Some(Value::Null));
+
+        assert_eq!(Value::from("12a").coerce_to(ValueKind::Number), None);
+        assert_eq!(Value::from("\"12\"").coerce_to(ValueKind::Number), None);
+        assert_eq!(Value::from("1").coerce_to(ValueKind::Bool), None);
+        assert_eq!(Value::Null.coerce_to(ValueKind::String), None);
+        assert_eq!(Value::Number(f64::NAN).coerce_to(ValueKind::String), None);
+        assert_eq!(Value::Number(f64::NEG_INFINITY).coerce_to(ValueKind::String), None);
+        assert_eq!(Value::array().build().coerce_to(ValueKind::Object), None);
+    }
+
+    #[test]
+    fn is_truthy() {
+        let falsy = [Value::Null, Value::Bool(false), Value::from(""), Value::Number(0.0), Value::Number(-0.0),
+            Value::Number(f64::NAN), Value::Integer(0), Value::RawNumber(String::from("0e10")),
+            Value::object().build(), Value::array().build()];
+        for value in &falsy {
+            assert!(!value.is_truthy(), "{:?}", value);
+        }
+
+        let truthy = [Value::Bool(true), Value::from("0"), Value::from("false"), Value::Number(0.5),
+            Value::Integer(-1), Value::RawNumber(String::from("1")), Value::array().push(Value::Null).build()];
+        for value in &truthy {
+            assert!(value.is_truthy(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn numeric_eq() {
+        let raw = |s: &str| Value::RawNumber(String::from(s));
+        assert!(Value::Number(1.0).numeric_eq(&raw("1")));
+        assert!(Value::Integer(1).numeric_eq(&Value::Number(1.0)));
+        assert!(!Value::Integer(i64::MAX).numeric_eq(&Value::Integer(i64::MAX - 1)));
+        assert!(raw("1.0").numeric_eq(&raw("10e-1")));
+        assert!(!raw("1").numeric_eq(&Value::String(String::from("1"))));
+
+        let a = Value::object().insert("x", raw("2")).insert("y", Value::array().push(raw("3e0")).build()).build();
+        let b = Value::object().insert("y", Value::array().push(3.0.into()).build()).insert("x", 2.0.into()).build();
+        assert!(a.numeric_eq(&b));
+        assert!(a != b);
+    }
+
+    #[test]
+    fn approx_eq() {
+        let computed = Value::array()
+            .push((0.1 + 0.2).into())
+            .push(Value::object().insert("x", 1.0000001.into()).build())
+            .build();
+        let expected: Value = "[0.3, { \"x\": 1 }]".parse().unwrap();
+        assert!(computed != expected);
+        assert!(computed.approx_eq(&expected, 1e-6));
+        assert!(!computed.approx_eq(&expected, 1e-9));
+
+        assert!(Value::Integer(1).approx_eq(&Value::Number(1.0), 0.0));
+        assert!(Value::Number(f64::INFINITY).approx_eq(&Value::Number(f64::INFINITY), 0.0));
+        assert!(!Value::from("a").approx_eq(&Value::from("b"), 1.0));
+    }
+
+    #[test]
+    fn structurally_eq() {
+        let a: Value = r#"{ "x": [1, { "y": null, "z": true }], "w": "v" }"#.parse().unwrap();
+        let b: Value = r#"{ "w": "v", "x": [1, { "z": true, "y": null }] }"#.parse().unwrap();
+        assert!(a.structurally_eq(&b));
+
+        let c: Value = r#"{ "w": "v", "x": [{ "z": true, "y": null }, 1] }"#.parse().unwrap();
+        assert!(!a.structurally_eq(&c));
+        assert!(!Value::Integer(1).structurally_eq(&Value::Number(1.0)));
+    }
+
+    #[test]
+    fn sort_keys() {
+        let value = Value::object()
+            .insert("b", Value::object().insert("z", Value::Null).insert("y", Value::Null).build())
+            .insert("a", 1.0.into())
+            .insert("c", Value::array().push(Value::object().insert("e", true.into()).insert("d", false.into()).build()).build())
+            .build();
+        let options = WriteOptions { sort_keys: true, ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), r#"{"a":1,"b":{"y":null,"z":null},"c":[{"d":false,"e":true}]}"#);
+    }
+
+    #[test]
+    fn ascii_only() {
+        let value = Value::from("aé\u{1D11E}\n");
+        assert_eq!(value.to_string(), "\"aé\u{1D11E}\\n\"");
+
+        let options = WriteOptions { ascii_only: true, ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), r#""a\u00E9\uD834\uDD1E\n""#);
+    }
+
+    #[test]
+    fn escape_json_string() {
+        assert_eq!(super::escape_json_string("a\"b\\c\n\u{1}é/"), r#"a\"b\\c\n\u0001é/"#);
+        for s in &["", "plain", "\"\\\x08\x0C\n\r\t\x1F", "\u{1D11E}"] {
+            assert_eq!(unescape_json_string(&super::escape_json_string(s)).unwrap(), *s);
+        }
+    }
+
+    #[test]
+    fn escape_slash() {
+        let value = Value::from("</script>");
+        assert_eq!(value.to_string(), r#""</script>""#);
+        let options = WriteOptions { escape_slash: true, ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), r#""<\/script>""#);
+    }
+
+    #[test]
+    fn skip_nulls() {
+        let value: Value = r#"{ "a": null, "b": [null, { "c": null }] }"#.parse().unwrap();
+        let options = WriteOptions { skip_nulls: true, ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), r#"{"b":[null,{}]}"#);
+    }
+
+    #[test]
+    fn precision() {
+        let value = Value::array()
+            .push(1234.5678.into())
+            .push(1.5.into())
+            .push(Value::Integer(12345))
+            .push(Value::RawNumber(String::from("1.23456")))
+            .build();
+
+        let options = WriteOptions { precision: Some(Precision::Significant(3)), ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), "[1230,1.5,12345,1.23456]");
+        let options = WriteOptions { precision: Some(Precision::Decimals(2)), ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), "[1234.57,1.50,12345,1.23456]");
+        let options = WriteOptions { precision: Some(Precision::Decimals(0)), ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), "[1235,2,12345,1.23456]");
+    }
+
+    #[test]
+    fn non_finite() {
+        let value = Value::array()
+            .push(Value::Number(f64::NAN))
+            .push(Value::Number(f64::INFINITY))
+            .push(Value::Number(f64::NEG_INFINITY))
+            .push(1.5.into())
+            .build();
+        assert_eq!(value.to_string(), "[null,null,null,1.5]");
+
+        let error = value.try_to_string_with(WriteOptions::default()).unwrap_err();
+        assert!(error.number.is_nan());
+        let error = Value::Number(f64::NEG_INFINITY).try_to_string_with(WriteOptions::default()).unwrap_err();
+        assert_eq!(error, NonFiniteError { number: f64::NEG_INFINITY });
+        assert_eq!(error.to_string(), "-inf has no JSON representation");
+        assert_eq!(Value::Number(1.5).try_to_string_with(WriteOptions::default()).unwrap(), "1.5");
+
+        let options = WriteOptions { non_finite_literals: true, ..WriteOptions::default() };
+        assert_eq!(value.to_string_with(options), "[NaN,Infinity,-Infinity,1.5]");
+        assert_eq!(value.try_to_string_with(options), Ok(value.to_string_with(options)));
+    }
+
+    #[test]
+    fn write_with_comments() {
+        let s = "// config\n{ /* a */ \"a\": [1, /* two */ 2], \"b/c\": // d\n true }";
+        let options = Options { allow_comments: true, ..Options::default() };
+        let (mut value, comments) = Parse::with_options(s, options).value_with_comments().unwrap();
+        value.get_mut("a").unwrap().get_index_mut(1).unwrap().take();
+        value.retain(|key, _| key != Some("b/c"));
+        let mut out = String::new();
+        value.write_with_comments(&mut out, &comments, WriteOptions::default()).unwrap();
+        assert_eq!(out, "// config\n{/* a */\"a\":[1,/* two */null]}");
+
+        let (reparsed, recomments) = Parse::with_options(&out, options).value_with_comments().unwrap();
+        assert_eq!(reparsed, value);
+        assert_eq!(recomments["/a/1"], ["/* two */"]);
+    }
+
+    #[test]
+    fn try_from() {
+        assert_eq!(String::try_from(Value::from("a")), Ok(String::from("a")));
+        assert_eq!(f64::try_from(Value::Integer(2)), Ok(2.0));
+        assert_eq!(f64::try_from(Value::RawNumber(String::from("1e1"))), Ok(10.0));
+        assert_eq!(bool::try_from(Value::Bool(true)), Ok(true));
+        assert_eq!(Array::try_from(Value::array().push(Value::Null).build()), Ok(vec![Value::Null]));
+        assert_eq!(Object::try_from(Value::object().build()), Ok(Object::new()));
+
+        let error = String::try_from(Value::Null).unwrap_err();
+        assert_eq!(error.to_string(), "expected string for value, found null");
+        assert_eq!(Object::try_from(Value::from(1.0)).unwrap_err().actual, ValueKind::Number);
+    }
+
+    #[test]
+    fn display() {
+        let value = Value::array()
+            .push("a \"quoted\"\n\u{1}string".into())
+            .push((-2.5).into())
+            .push(Value::object().insert("k", Value::Null).build())
+            .push(Value::Number(f64::NAN))
+            .build();
+        assert_eq!(value.to_string(), r#"["a \"quoted\"\n\u0001string",-2.5,{"k":null},null]"#);
+        assert_eq!(value.to_bytes(), value.to_string().into_bytes());
+    }
+
+    #[test]
+    fn deep_display() {
+        let depth = 100_000;
+        let mut value = Value::Null;
+        for _ in 0..depth {
+            value = Value::Array(vec![value]);
+        }
+        assert_eq!(value.to_string(), format!("{}null{}", "[".repeat(depth), "]".repeat(depth)));
+
+        // Dropping the value recursively would overflow the stack, so take it apart first.
+        while let Value::Array(mut array) = value {
+            value = array.pop().unwrap();
+        }
+    }
+
+    #[test]
+    fn primitive_eq() {
+        let (string, number, bool_) = (Value::from("ok"), Value::from(3.0), Value::from(true));
+        assert!(string == "ok" && number == 3.0 && bool_ == true);
+        assert!(string != "ko" && number != 4.0 && bool_ != false);
+        assert!(Value::from("true") != true);
+        assert!(Value::from("3") != 3.0);
+        assert!(Value::Integer(3) != 3.0);
+        assert!(Value::Null != "null");
+        assert!(Value::Null != false);
+    }
+
+    #[test]
+    fn from_str() {
+        let value: Value = r#" { "a": [true] } "#.parse().unwrap();
+        assert_eq!(value, Value::object().insert("a", Value::array().push(true.into()).build()).build());
+
+        let error = "[1, }".parse::<Value>().unwrap_err();
+        let expected = FromStrError { kind: ParseErrorKind::UnexpectedToken, lex_error: None, span: String::from("}") };
+        assert_eq!(error, expected);
+        assert_eq!(error.to_string(), "unexpected token `}`");
+
+        let boxed = || -> Result<Value, Box<dyn error::Error>> { Ok("[".parse::<Value>()?) };
+        assert_eq!(boxed().unwrap_err().to_string(), "unexpected end of input");
+    }
+}
@@ -1,24 +1,53 @@
-use std::{char, str};
+use std::{char, error, fmt, str};
+use options::{Options, NumberMode};
 
 /// A JSON lexer over a UTF-8 string.
 ///
 /// The lexer produces JSON tokens according to RFC 7159.
 /// When it encounters invalid tokens, it returns an error token that includes
-/// the invalid bytes in its span. The parser can use this for error recovery.
-pub(crate) struct Lex<'source> {
+/// the invalid bytes in its span and the reason they are invalid. The parser can use this for
+/// error recovery. `try_token` returns the same errors as a `LexError`.
+///
+/// `Lex`, `Token` and `TokenKind` are a stable public API for tools that work with tokens
+/// rather than values, such as formatters. New kinds of tokens may be added in a minor release,
+/// so `TokenKind` is non-exhaustive. As an iterator, the lexer yields every token up to but not
+/// including `End`.
+pub struct Lex<'source> {
+    input: &'source [u8],
     source: &'source [u8],
+    options: Options,
+
+    /// Cleared buffers to decode strings into, rather than allocating new ones.
+    strings: Vec<String>,
+    /// Skip decoding strings without escape sequences, for callers that borrow them from their
+    /// spans instead. Such strings are produced as empty `TokenKind::String`s.
+    borrow_strings: bool,
+    /// Produce whitespace as tokens, rather than skipping it.
+    whitespace: bool,
+    /// Produce strings as `TokenKind::RawString`, without decoding or validating their contents.
+    raw_strings: bool,
+    /// The comments skipped before the last token.
+    comments: Vec<&'source str>,
+    /// The number of tokens produced so far, not including `End`.
+    #[cfg(feature = "stats")]
+    tokens: usize,
 }
 
 /// A single JSON token.
-#[derive(PartialEq, Debug)]
-pub(crate) struct Token<'source> {
-    pub(crate) span: &'source str,
-    pub(crate) kind: TokenKind,
+#[derive(Clone, PartialEq, Debug)]
+pub struct Token<'source> {
+    /// The token's source text, not including any preceding whitespace.
+    ///
+    /// For a string, this is the literal exactly as written, with its quotes and escape
+    /// sequences, so a formatter can re-emit it byte-for-byte alongside the decoded value.
+    pub span: &'source str,
+    pub kind: TokenKind,
 }
 
 /// A kind of token, including its payload.
-#[derive(PartialEq, Debug)]
-pub(crate) enum TokenKind {
+#[derive(Clone, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum TokenKind {
     LeftBrace,
     RightBrace,
     LeftBracket,
@@ -26,33 +55,233 @@ pub(crate) enum TokenKind {
     Colon,
     Comma,
 
+    /// A string, with its escape sequences decoded.
     String(String),
+    /// A string whose contents were not decoded, only produced after `Lex::raw_strings`. The
+    /// token's span is the literal, with its quotes and escape sequences.
+    RawString,
     Number(f64),
+    /// An integer, produced instead of `Number` with `NumberMode::Integer`.
+    Integer(i64),
+    /// A number's source text, produced instead of `Number` with `NumberMode::Raw`, and for
+    /// integers that overflow with `NumberMode::BigInteger`.
+    RawNumber(String),
     Bool(bool),
     Null,
 
-    Error,
+    /// A run of whitespace, with the number of newlines it contains. Only produced after
+    /// `Lex::whitespace_tokens`.
+    Whitespace(usize),
+
+    /// Invalid input, with the reason it is invalid. The token's span covers the invalid bytes.
+    Error(LexErrorKind),
+    /// The end of the input. The token's span is empty.
     End,
 }
 
+/// The reason a token is invalid. More specific reasons may be added.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum LexErrorKind {
+    /// A string with no closing quote before the end of the input.
+    UnterminatedString,
+    /// A string with an invalid unicode escape or an unknown escape sequence, when the options
+    /// reject them.
+    InvalidEscape,
+    /// A malformed number, or an integer the options require to be exact or to fit an `i64`.
+    InvalidNumber,
+    /// Any other invalid input, such as an unknown character or a misspelled keyword.
+    InvalidToken,
+}
+
+/// An invalid token, for callers that use the lexer directly. Produced by `Lex::try_token`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LexError<'source> {
+    pub kind: LexErrorKind,
+    /// The invalid bytes.
+    pub span: &'source str,
+    /// The byte offset of the span in the lexer's input.
+    pub offset: usize,
+}
+
+impl LexErrorKind {
+    /// A short description of this kind of error, such as "invalid number".
+    pub(crate) fn reason(self) -> &'static str {
+        match self {
+            LexErrorKind::UnterminatedString => "unterminated string",
+            LexErrorKind::InvalidEscape => "invalid escape sequence in string",
+            LexErrorKind::InvalidNumber => "invalid number",
+            LexErrorKind::InvalidToken => "invalid token",
+        }
+    }
+}
+
+impl<'source> fmt::Display for LexError<'source> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} `{}` at offset {}", self.kind.reason(), self.span, self.offset)
+    }
+}
+
+impl<'source> error::Error for LexError<'source> {}
+
 impl<'source> Lex<'source> {
     /// Create a new lexer for a JSON string.
-    pub(crate) fn new(source: &'source str) -> Lex<'source> {
-        let source = source.as_bytes();
-        Lex { source }
+    pub fn new(source: &'source str) -> Lex<'source> {
+        Self::with_options(source, Options::default())
+    }
+
+    /// Create a new lexer for a JSON string, with the given options.
+    pub fn with_options(source: &'source str, options: Options) -> Lex<'source> {
+        Self::with_strings(source, options, Vec::new())
+    }
+
+    /// Create a new lexer for a JSON string, which decodes strings into the given buffers.
+    pub(crate) fn with_strings(source: &'source str, options: Options, strings: Vec<String>) -> Lex<'source> {
+        let input = source.as_bytes();
+        let mut source = match *input {
+            [0xEF, 0xBB, 0xBF, ref rest..] if options.allow_bom => rest,
+            _ => input,
+        };
+        if let [b'#', ..] = *source {
+            if options.allow_hash_line {
+                let end = source.iter().position(|&b| b == b'\n').map_or(source.len(), |newline| newline + 1);
+                source = &source[end..];
+            }
+        }
+        Lex {
+            input,
+            source,
+            options,
+            strings,
+            borrow_strings: false,
+            whitespace: false,
+            raw_strings: false,
+            comments: Vec::new(),
+            #[cfg(feature = "stats")]
+            tokens: 0,
+        }
+    }
+
+    /// Produce strings as `TokenKind::RawString`, rather than decoding them into a
+    /// `TokenKind::String`.
+    ///
+    /// This skips allocating for callers that only need the literals, such as a search index.
+    /// Escape sequences are not checked here, but `decode_string` decodes a literal later and
+    /// reports any invalid ones. Unterminated strings are still errors.
+    pub fn raw_strings(&mut self) {
+        self.raw_strings = true;
+    }
+
+    /// Stop decoding strings without escape sequences.
+    pub(crate) fn borrow_strings(&mut self) {
+        self.borrow_strings = true;
+    }
+
+    /// Produce whitespace between tokens as `TokenKind::Whitespace`, rather than skipping it.
+    ///
+    /// This lets a formatter preserve the blank lines in its input.
+    pub fn whitespace_tokens(&mut self) {
+        self.whitespace = true;
+    }
+
+    /// The comments the lexer skipped before the last token it read, with their `//` or `/* */`
+    /// delimiters. These are only recognized with `Options::allow_comments`.
+    ///
+    /// With `whitespace_tokens`, these are the comments in the last `Whitespace` token.
+    pub fn comments(&self) -> &[&'source str] {
+        &self.comments
+    }
+
+    /// The number of tokens the lexer has produced, not including `End`.
+    #[cfg(feature = "stats")]
+    pub(crate) fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    /// Return the lexer's remaining string buffers.
+    pub(crate) fn into_strings(self) -> Vec<String> {
+        self.strings
+    }
+
+    /// The byte offset of the lexer's current position in its input.
+    pub fn position(&self) -> usize {
+        self.source.as_ptr() as usize - self.input.as_ptr() as usize
+    }
+
+    /// The input after the lexer's current position, which it has not read yet.
+    pub fn remaining(&self) -> &'source str {
+        unsafe { str::from_utf8_unchecked(self.source) }
+    }
+
+    /// The byte offset of a token's span in the lexer's input.
+    ///
+    /// Panics if the span is not part of the lexer's input, such as a span from another lexer.
+    pub fn offset(&self, span: &str) -> usize {
+        self.try_offset(span).expect("the span is not part of the lexer's input")
+    }
+
+    /// The byte offset of a span in the lexer's input, or None if it is not part of the input.
+    pub(crate) fn try_offset(&self, span: &str) -> Option<usize> {
+        let offset = (span.as_ptr() as usize).checked_sub(self.input.as_ptr() as usize)?;
+        if offset > self.input.len() || span.len() > self.input.len() - offset {
+            return None;
+        }
+        Some(offset)
+    }
+
+    /// The one-based line and column of a token's span in the lexer's input, with columns
+    /// counted in characters.
+    ///
+    /// Panics if the span is not part of the lexer's input, such as a span from another lexer.
+    pub fn line_column(&self, span: &str) -> (usize, usize) {
+        // The input was a `str`, and slicing it checks that the span starts on a codepoint
+        // boundary.
+        let input = unsafe { str::from_utf8_unchecked(self.input) };
+        let before = &input[..self.offset(span)];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line = before.matches('\n').count() + 1;
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
     }
 
     /// Read the next token from the lexer.
-    pub(crate) fn token(&mut self) -> Token<'source> {
+    pub fn token(&mut self) -> Token<'source> {
         // Skip any whitespace before a token.
+        let mut rest = self.source;
+        let mut newlines = 0;
+        self.comments.clear();
         loop {
-            match *self.source {
-                [b, ref rest..] if [b' ', b'\t', b'\r', b'\n'].contains(&b) => self.source = rest,
+            match *rest {
+                [b'\n', ref tail..] => { rest = tail; newlines += 1; }
+                [b, ref tail..] if [b' ', b'\t', b'\r'].contains(&b) => rest = tail,
+                [b'\x0C', ref tail..] | [b'\x0B', ref tail..] if self.options.allow_extra_whitespace => rest = tail,
+
+                // A line comment ends before its newline, which is skipped as whitespace.
+                [b'/', b'/', ref tail..] if self.options.allow_comments => {
+                    let len = 2 + tail.iter().position(|&b| b == b'\n').unwrap_or(tail.len());
+                    self.comments.push(unsafe { str::from_utf8_unchecked(&rest[..len]) });
+                    rest = &rest[len..];
+                }
+                // An unterminated block comment is left for the invalid token below.
+                [b'/', b'*', ref tail..] if self.options.allow_comments => {
+                    let len = match tail.windows(2).position(|end| end == b"*/") {
+                        Some(end) => 2 + end + 2,
+                        None => break,
+                    };
+                    newlines += rest[..len].iter().filter(|&&b| b == b'\n').count();
+                    self.comments.push(unsafe { str::from_utf8_unchecked(&rest[..len]) });
+                    rest = &rest[len..];
+                }
                 _ => break,
             }
         }
+        if self.whitespace && rest.len() < self.source.len() {
+            return self.span(TokenKind::Whitespace(newlines), rest);
+        }
+        self.source = rest;
 
         // Determine the token kind by its first byte.
+        let options = &self.options;
         let (kind, rest) = match *self.source {
             [b'{', ref rest..] => (TokenKind::LeftBrace, rest),
             [b'}', ref rest..] => (TokenKind::RightBrace, rest),
@@ -61,17 +290,49 @@ impl<'source> Lex<'source> {
             [b':', ref rest..] => (TokenKind::Colon, rest),
             [b',', ref rest..] => (TokenKind::Comma, rest),
 
-            [b'"', ref rest..] => Self::string(rest),
-            ref rest @ [b'-', ..] | ref rest @ [b'0'..=b'9', ..] => Self::number(rest),
-            [b't', b'r', b'u', b'e', ref rest..] => (TokenKind::Bool(true), rest),
-            [b'f', b'a', b'l', b's', b'e', ref rest..] => (TokenKind::Bool(false), rest),
-            [b'n', b'u', b'l', b'l', ref rest..] => (TokenKind::Null, rest),
+            [b'"', ref rest..] if self.raw_strings => Self::raw_string(rest),
+            [b'"', ref rest..] if self.borrow_strings => match Self::unescaped(rest) {
+                Some(rest) => (TokenKind::String(String::new()), rest),
+                None => Self::string(rest, options, String::new()),
+            },
+            [b'"', ref rest..] => {
+                let string = self.strings.pop().unwrap_or_default();
+                Self::string(rest, options, string)
+            }
+            ref rest @ [b'-', ..] | ref rest @ [b'0'..=b'9', ..] => match Self::number(rest, options) {
+                (TokenKind::Error(kind), rest) => (TokenKind::Error(kind), Self::invalid(rest, options)),
+                token => token,
+            },
+            [b't', b'r', b'u', b'e', ref rest..] => Self::keyword(TokenKind::Bool(true), rest, options),
+            [b'f', b'a', b'l', b's', b'e', ref rest..] => Self::keyword(TokenKind::Bool(false), rest, options),
+            [b'n', b'u', b'l', b'l', ref rest..] => Self::keyword(TokenKind::Null, rest, options),
 
-            [_, ref rest..] => (TokenKind::Error, rest),
+            [_, ref rest..] => (TokenKind::Error(LexErrorKind::InvalidToken), Self::invalid(rest, options)),
             [ref rest..] => (TokenKind::End, rest),
         };
+        self.span(kind, rest)
+    }
+
+    /// Read the next token from the lexer, or the reason it is invalid.
+    pub fn try_token(&mut self) -> Result<Token<'source>, LexError<'source>> {
+        match self.token() {
+            Token { span, kind: TokenKind::Error(kind) } => {
+                Err(LexError { kind, span, offset: self.offset(span) })
+            }
+            token => Ok(token),
+        }
+    }
+
+    /// Build a token from the post-whitespace position and the end of its span, and move the
+    /// lexer past it.
+    fn span(&mut self, kind: TokenKind, rest: &'source [u8]) -> Token<'source> {
+        #[cfg(feature = "stats")]
+        {
+            if kind != TokenKind::End {
+                self.tokens += 1;
+            }
+        }
 
-        // Build the token's span from the post-whitespace position and the current position.
         let len = rest.as_ptr() as usize - self.source.as_ptr() as usize;
         let span = unsafe { str::from_utf8_unchecked(self.source.get_unchecked(..len)) };
 
@@ -79,13 +340,64 @@ impl<'source> Lex<'source> {
         Token { span, kind }
     }
 
+    /// End a keyword, or if it runs into more characters, such as `truex`, make the whole run
+    /// a single invalid token.
+    fn keyword(kind: TokenKind, rest: &'source [u8], options: &Options) -> (TokenKind, &'source [u8]) {
+        if !Self::ends_token(rest, options) {
+            return (TokenKind::Error(LexErrorKind::InvalidToken), Self::invalid(rest, options));
+        }
+        (kind, rest)
+    }
+
+    /// Read the rest of an invalid token, up to the next whitespace or structural character.
+    ///
+    /// This gives the parser a sensible place to resume after an error.
+    fn invalid(mut source: &'source [u8], options: &Options) -> &'source [u8] {
+        while !Self::ends_token(source, options) {
+            source = &source[1..];
+        }
+        source
+    }
+
+    /// Whether a keyword or invalid token ends before `source`, at the end of the input or at
+    /// the same whitespace and comments the lexer skips between tokens or a structural
+    /// character.
+    ///
+    /// Every byte that ends a token is ASCII, so the next token always starts on a codepoint
+    /// boundary.
+    fn ends_token(source: &[u8], options: &Options) -> bool {
+        match *source {
+            [b' ', ..] | [b'\t', ..] | [b'\r', ..] | [b'\n', ..] => true,
+            [b'\x0C', ..] | [b'\x0B', ..] => options.allow_extra_whitespace,
+            [b'/', b'/', ..] | [b'/', b'*', ..] => options.allow_comments,
+            [b'{', ..] | [b'}', ..] | [b'[', ..] | [b']', ..] => true,
+            [b':', ..] | [b',', ..] | [b'"', ..] => true,
+            [_, ..] => false,
+            [] => true,
+        }
+    }
+
     /// Read the rest of a string, after the open quote.
     ///
-    /// Replaces invalid unicode escape sequences with U+FFFD.
-    /// Returns TokenKind::Error for unterminated strings.
-    fn string(mut source: &'source [u8]) -> (TokenKind, &'source [u8]) {
-        let mut string = String::new();
+    /// Replaces invalid unicode escape sequences with U+FFFD, or with
+    /// `!options.replace_invalid_unicode` returns `LexErrorKind::InvalidEscape` for the whole
+    /// string. Likewise keeps unknown escape sequences literally, or with
+    /// `!options.allow_unknown_escapes` returns `LexErrorKind::InvalidEscape`.
+    /// Returns `LexErrorKind::UnterminatedString` for unterminated strings.
+    ///
+    /// The string is decoded into `string`, which must be empty.
+    fn string(mut source: &'source [u8], options: &Options, mut string: String) -> (TokenKind, &'source [u8]) {
+        let mut valid = true;
         loop {
+            // Copy runs of plain ASCII all at once.
+            let run = source.iter()
+                .position(|&b| b == b'"' || b == b'\\' || b >= 0x80)
+                .unwrap_or(source.len());
+            if run > 0 {
+                string.push_str(unsafe { str::from_utf8_unchecked(source.get_unchecked(..run)) });
+                source = &source[run..];
+            }
+
             match *source {
                 // Closing quote.
                 [b'"', ref rest..] => { source = rest; break; }
@@ -102,8 +414,16 @@ impl<'source> Lex<'source> {
                 [b'\\', b'u', ref rest..] => {
                     let (c, rest) = Self::unicode_escape(rest);
                     source = rest;
-                    string.push(c);
+                    match c {
+                        Some(c) => string.push(c),
+                        None if options.replace_invalid_unicode => string.push('\u{FFFD}'),
+                        None => valid = false,
+                    }
                 }
+                // Unknown escape sequences keep their backslash, and the character after it is
+                // read as usual.
+                [b'\\', ref rest..] if options.allow_unknown_escapes => { source = rest; string.push('\\'); }
+                [b'\\', ref rest..] => { source = rest; valid = false; }
 
                 // UTF-8 codepoints.
                 // TODO: replace this with library code somehow?
@@ -128,42 +448,71 @@ impl<'source> Lex<'source> {
                     string.push_str(s);
                 }
 
-                // The input is valid UTF-8, so this should never happen.
-                [_, _..] => unreachable!(),
-
                 // Unterminated string.
-                [ref rest..] => return (TokenKind::Error, rest),
+                // The input is valid UTF-8, so no other bytes can appear here, but if they somehow
+                // did the string would be treated as unterminated rather than panicking.
+                _ => return (TokenKind::Error(LexErrorKind::UnterminatedString), &source[source.len()..]),
             }
         }
 
+        if !valid {
+            return (TokenKind::Error(LexErrorKind::InvalidEscape), source);
+        }
         (TokenKind::String(string), source)
     }
 
+    /// Skip the rest of a string, after the open quote, without decoding it.
+    fn raw_string(mut source: &'source [u8]) -> (TokenKind, &'source [u8]) {
+        loop {
+            match *source {
+                [b'"', ref rest..] => return (TokenKind::RawString, rest),
+                // An escaped quote does not end the string. Any other byte after a backslash is
+                // either ASCII or the start of a codepoint, which is skipped like the rest.
+                [b'\\', _, ref rest..] => source = rest,
+                [_, ref rest..] => source = rest,
+                [] => return (TokenKind::Error(LexErrorKind::UnterminatedString), source),
+            }
+        }
+    }
+
+    /// Skip the rest of a string, if it has no escape sequences and is terminated.
+    fn unescaped(mut source: &'source [u8]) -> Option<&'source [u8]> {
+        loop {
+            match *source {
+                [b'"', ref rest..] => return Some(rest),
+                [b'\\', ..] | [] => return None,
+                [_, ref rest..] => source = rest,
+            }
+        }
+    }
+
     /// Read the rest of a Unicode escape sequence, after the \u.
     ///
     /// Reads two escape sequences if the first is a leading surrogate.
-    /// Replaces invalid codepoints, including incomplete escape sequences and
-    /// unpaired surrogates, with U+FFFD.
-    fn unicode_escape(mut source: &'source [u8]) -> (char, &'source [u8]) {
+    /// Returns None for invalid codepoints, including incomplete escape sequences and
+    /// unpaired surrogates.
+    fn unicode_escape(mut source: &'source [u8]) -> (Option<char>, &'source [u8]) {
         let code_point = match Self::code_unit(source) {
             (Some(s1 @ 0xD800..=0xDBFF), rest) => {
-                let (s2, rest) = match *rest {
-                    [b'\\', b'u', ref rest..] => Self::code_unit(rest),
-                    _ => (None, rest),
-                };
                 source = rest;
 
-                if let Some(s2 @ 0xDC00..=0xDFFF) = s2 {
-                    Some(0x1_0000 + (((s1 - 0xD800) << 10) | (s2 - 0xDC00)))
-                } else {
-                    None
+                // Only consume a second escape sequence if it completes the pair, so anything
+                // else (such as another leading surrogate) is read on its own.
+                match *rest {
+                    [b'\\', b'u', ref rest..] => match Self::code_unit(rest) {
+                        (Some(s2 @ 0xDC00..=0xDFFF), rest) => {
+                            source = rest;
+                            Some(0x1_0000 + (((s1 - 0xD800) << 10) | (s2 - 0xDC00)))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
                 }
             }
             (code_unit, rest) => { source = rest; code_unit }
         };
 
-        let c = code_point.and_then(char::from_u32).unwrap_or('\u{FFFD}');
-        (c, source)
+        (code_point.and_then(char::from_u32), source)
     }
 
     /// Read the body of a JSON unicode escape sequence.
@@ -178,11 +527,11 @@ impl<'source> Lex<'source> {
                     source = rest;
                     (b - b'0') as u16
                 }
-                [b @ b'A'..=b'Z', ref rest..] => {
+                [b @ b'A'..=b'F', ref rest..] => {
                     source = rest;
                     (b - b'A') as u16 + 10
                 }
-                [b @ b'a'..=b'z', ref rest..] => {
+                [b @ b'a'..=b'f', ref rest..] => {
                     source = rest;
                     (b - b'a') as u16 + 10
                 }
@@ -195,14 +544,22 @@ impl<'source> Lex<'source> {
 
     /// Read a number.
     ///
-    /// Returns TokenKind::Error on invalid numbers.
-    fn number(mut source: &'source [u8]) -> (TokenKind, &'source [u8]) {
+    /// Returns `LexErrorKind::InvalidNumber` on invalid numbers, and on integers that overflow in
+    /// `NumberMode::Integer`. Numbers with a fraction or exponent are always floats.
+    /// With `options.exact_integers`, also returns `LexErrorKind::InvalidNumber` on integers that
+    /// would lose precision as floats.
+    fn number(mut source: &'source [u8], options: &Options) -> (TokenKind, &'source [u8]) {
+        let start = source;
+        let mut integral = true;
+        let mut dropped_digits = false;
+
         let positive = match *source {
             [b'-', ref rest..] => { source = rest; false }
             _ => true,
         };
 
         let mut significand: u64;
+        let mut exponent: i32 = 0;
         match *source {
             [b'0', ref rest..] => {
                 source = rest;
@@ -214,27 +571,37 @@ impl<'source> Lex<'source> {
                 while let [b @ b'0'..=b'9', ref rest..] = *source {
                     source = rest;
 
+                    // Digits beyond the significand's precision only scale it.
                     let digit = (b - b'0') as u64;
-                    significand = 10 * significand + digit;
+                    match significand.checked_mul(10).and_then(|s| s.checked_add(digit)) {
+                        Some(s) => significand = s,
+                        None => {
+                            exponent = exponent.saturating_add(1);
+                            dropped_digits |= digit != 0;
+                        }
+                    }
                 }
             }
-            _ => return (TokenKind::Error, source),
+            _ => return (TokenKind::Error(LexErrorKind::InvalidNumber), source),
         };
 
-        let mut exponent: i32 = 0;
         if let [b'.', ref rest..] = *source {
             source = rest;
+            integral = false;
             let mut any_digits = false;
             while let [b @ b'0'..=b'9', ref rest..] = *source {
                 source = rest;
                 any_digits = true;
 
+                // Digits beyond the significand's precision are dropped.
                 let digit = (b - b'0') as u64;
-                significand = 10 * significand + digit;
-                exponent -= 1;
+                if let Some(s) = significand.checked_mul(10).and_then(|s| s.checked_add(digit)) {
+                    significand = s;
+                    exponent -= 1;
+                }
             }
             if !any_digits {
-                return (TokenKind::Error, source);
+                return (TokenKind::Error(LexErrorKind::InvalidNumber), source);
             }
         }
 
@@ -245,6 +612,7 @@ impl<'source> Lex<'source> {
         };
         if has_exponent {
             source = rest;
+            integral = false;
 
             let positive = match *source {
                 [b'+', ref rest..] => { source = rest; true }
@@ -258,37 +626,90 @@ impl<'source> Lex<'source> {
                 source = rest;
                 any_digits = true;
 
+                // Exponents this large over- or underflow anyway, so saturating is harmless.
                 let digit = (b - b'0') as i32;
-                explicit_exponent = 10 * explicit_exponent + digit;
+                explicit_exponent = explicit_exponent.saturating_mul(10).saturating_add(digit);
             }
             if !any_digits {
-                return (TokenKind::Error, source);
+                return (TokenKind::Error(LexErrorKind::InvalidNumber), source);
             }
 
             if positive {
-                exponent += explicit_exponent;
+                exponent = exponent.saturating_add(explicit_exponent);
             } else {
-                exponent -= explicit_exponent;
+                exponent = exponent.saturating_sub(explicit_exponent);
             }
         }
 
+        // Numbers are always validated, but other modes are built from their original text.
+        let len = source.as_ptr() as usize - start.as_ptr() as usize;
+        let text = unsafe { str::from_utf8_unchecked(start.get_unchecked(..len)) };
+        match options.number_mode {
+            NumberMode::Raw => return (TokenKind::RawNumber(String::from(text)), source),
+            NumberMode::Integer if integral => return match text.parse() {
+                Ok(integer) => (TokenKind::Integer(integer), source),
+                Err(_) => (TokenKind::Error(LexErrorKind::InvalidNumber), source),
+            },
+            NumberMode::BigInteger if integral => return match text.parse() {
+                Ok(integer) => (TokenKind::Integer(integer), source),
+                Err(_) => (TokenKind::RawNumber(String::from(text)), source),
+            },
+            _ => {}
+        }
+        if integral && options.exact_integers && (dropped_digits || !Self::exact(significand, exponent)) {
+            return (TokenKind::Error(LexErrorKind::InvalidNumber), source);
+        }
+
+        // Stop scaling once the magnitude reaches infinity or zero, so huge exponents are cheap.
         let mut magnitude = significand as f64;
-        for _ in 0..i32::abs(exponent) {
+        while exponent != 0 && magnitude != 0.0 && magnitude.is_finite() {
             if exponent > 0 {
                 magnitude *= 10.0;
+                exponent -= 1;
             } else {
                 magnitude /= 10.0;
+                exponent += 1;
             }
         }
         let value = if positive { magnitude } else { -magnitude };
 
         (TokenKind::Number(value), source)
     }
+
+    /// Whether the integer `significand * 10^exponent` can be represented exactly as an f64.
+    fn exact(significand: u64, exponent: i32) -> bool {
+        if significand == 0 {
+            return true;
+        }
+
+        // Factors of two only affect the float's exponent, so only the odd part must fit in
+        // its 53-bit significand. Each factor of ten adds a factor of five to the odd part.
+        let mut odd = significand >> significand.trailing_zeros();
+        for _ in 0..exponent {
+            if odd > 1 << 53 {
+                break;
+            }
+            odd *= 5;
+        }
+        odd <= 1 << 53
+    }
+}
+
+impl<'source> Iterator for Lex<'source> {
+    type Item = Token<'source>;
+
+    fn next(&mut self) -> Option<Token<'source>> {
+        match self.token() {
+            Token { kind: TokenKind::End, .. } => None,
+            token => Some(token),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use lex::{Lex, Token, TokenKind};
+    use lex::{Lex, Token, TokenKind, LexError, LexErrorKind};
+    use options::Options;
 
     #[test]
     fn simple() {
@@ -322,4 +743,367 @@ mod tests {
 
         assert_eq!(lex.token(), Token { span: &s[46..47], kind: TokenKind::RightBrace });
     }
+
+    #[test]
+    fn hostile_input() {
+        // Invalid non-ASCII characters are never split, so spans stay valid.
+        let s = "é[";
+        let mut lex = Lex::new(s);
+        assert_eq!(lex.token(), Token { span: "é", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!(lex.token(), Token { span: "[", kind: TokenKind::LeftBracket });
+
+        // Non-hex digits end a unicode escape rather than overflowing it.
+        let s = r#""\uZZZZ""#;
+        assert_eq!(Lex::new(s).token().kind, TokenKind::String(String::from("\u{FFFD}ZZZZ")));
+
+        // Huge exponents neither overflow nor take time proportional to their value.
+        assert_eq!(Lex::new("1e99999999999").token().kind, TokenKind::Number(f64::INFINITY));
+        assert_eq!(Lex::new("1e-99999999999").token().kind, TokenKind::Number(0.0));
+    }
+
+    #[test]
+    fn long_exponents() {
+        let number = |s: &str| Lex::new(s).token().kind;
+        let nines = "9".repeat(10_000);
+        assert_eq!(number(&format!("1e{}", nines)), TokenKind::Number(f64::INFINITY));
+        assert_eq!(number(&format!("-1e+{}", nines)), TokenKind::Number(f64::NEG_INFINITY));
+        assert_eq!(number(&format!("1e-{}", nines)), TokenKind::Number(0.0));
+        assert_eq!(number(&format!("0e{}", nines)), TokenKind::Number(0.0));
+        assert_eq!(number("1e2147483648"), TokenKind::Number(f64::INFINITY));
+        assert_eq!(number("1e-2147483649"), TokenKind::Number(0.0));
+
+        // Leading zeros do not count towards the exponent, and digits dropped from a long
+        // significand are balanced by a negative exponent.
+        assert_eq!(number("1e00000000000000000000000002"), TokenKind::Number(100.0));
+        assert_eq!(number(&format!("1{}e-400", "0".repeat(400))), TokenKind::Number(1.0));
+    }
+
+    #[test]
+    fn exact_integers() {
+        let exact = Options { exact_integers: true, ..Options::default() };
+        assert_eq!(Lex::new("9007199254740993").token().kind, TokenKind::Number(9007199254740992.0));
+        let error = TokenKind::Error(LexErrorKind::InvalidNumber);
+        assert_eq!(Lex::with_options("9007199254740993", exact).token().kind, error);
+        assert_eq!(Lex::with_options("-9007199254740993", exact).token().kind, error);
+        assert_eq!(Lex::with_options("100000000000000000000001", exact).token().kind, error);
+
+        // Large powers of two and ten still fit, as do numbers with fractions or exponents.
+        let exact_kind = |s| Lex::with_options(s, exact).token().kind;
+        assert_eq!(exact_kind("9007199254740992"), TokenKind::Number(9007199254740992.0));
+        assert_eq!(exact_kind("18014398509481984"), TokenKind::Number(18014398509481984.0));
+        assert_eq!(exact_kind("1000000000000000000000"), TokenKind::Number(1e21));
+        assert_eq!(exact_kind("9007199254740993e0"), TokenKind::Number(9007199254740992.0));
+        assert_eq!(exact_kind("0"), TokenKind::Number(0.0));
+    }
+
+    #[test]
+    fn hash_line() {
+        let s = "#!/usr/bin/env tool\n{}";
+        let options = Options { allow_hash_line: true, ..Options::default() };
+        let mut lex = Lex::with_options(s, options);
+        let token = lex.token();
+        assert_eq!(token.kind, TokenKind::LeftBrace);
+        assert_eq!(lex.offset(token.span), s.len() - 2);
+
+        assert_eq!(Lex::with_options("# comment", options).token().kind, TokenKind::End);
+        let token = Token { span: "#", kind: TokenKind::Error(LexErrorKind::InvalidToken) };
+        assert_eq!(Lex::with_options("{}\n#", options).nth(2), Some(token));
+
+        let token = Lex::with_options(s, Options::strict()).token();
+        assert_eq!(token, Token { span: "#!/usr/bin/env", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+    }
+
+    #[test]
+    fn whitespace_tokens() {
+        let s = "{\n  \"a\": 1,\r\n\n  \"b\": 2}";
+        let mut lex = Lex::new(s);
+        lex.whitespace_tokens();
+        let kinds: Vec<_> = lex.map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBrace,
+            TokenKind::Whitespace(1),
+            TokenKind::String(String::from("a")),
+            TokenKind::Colon,
+            TokenKind::Whitespace(0),
+            TokenKind::Number(1.0),
+            TokenKind::Comma,
+            TokenKind::Whitespace(2),
+            TokenKind::String(String::from("b")),
+            TokenKind::Colon,
+            TokenKind::Whitespace(0),
+            TokenKind::Number(2.0),
+            TokenKind::RightBrace,
+        ]);
+
+        let mut lex = Lex::new(" \n");
+        lex.whitespace_tokens();
+        assert_eq!(lex.token(), Token { span: " \n", kind: TokenKind::Whitespace(1) });
+        assert_eq!(lex.token(), Token { span: "", kind: TokenKind::End });
+    }
+
+    #[test]
+    fn extra_whitespace() {
+        let s = "[1,\x0C2\x0B]";
+        // Form feed is not whitespace in strict mode, so it starts an invalid token.
+        let tokens: Vec<_> = Lex::with_options(s, Options::strict()).collect();
+        assert_eq!(tokens[3], Token { span: "\x0C2\x0B", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!(tokens.len(), 5);
+
+        let kinds: Vec<_> = Lex::with_options(s, Options::lenient()).map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBracket,
+            TokenKind::Number(1.0),
+            TokenKind::Comma,
+            TokenKind::Number(2.0),
+            TokenKind::RightBracket,
+        ]);
+
+        // Keywords end at the extra whitespace too, like numbers.
+        let lex = Lex::with_options("[true\x0C,null\x0B]", Options::lenient());
+        let kinds: Vec<_> = lex.map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBracket,
+            TokenKind::Bool(true),
+            TokenKind::Comma,
+            TokenKind::Null,
+            TokenKind::RightBracket,
+        ]);
+        let token = Lex::with_options("true\x0C", Options::strict()).token();
+        assert_eq!(token, Token { span: "true\x0C", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+    }
+
+    #[test]
+    fn comments() {
+        let s = "// a\n[1, /* b\n c */ 2// d\n]";
+        let options = Options { allow_comments: true, ..Options::default() };
+        let mut lex = Lex::with_options(s, options);
+        assert_eq!(lex.token().kind, TokenKind::LeftBracket);
+        assert_eq!(lex.comments(), ["// a"]);
+        assert_eq!(lex.token().kind, TokenKind::Number(1.0));
+        assert!(lex.comments().is_empty());
+        assert_eq!(lex.token().kind, TokenKind::Comma);
+        assert_eq!(lex.token().kind, TokenKind::Number(2.0));
+        assert_eq!(lex.comments(), ["/* b\n c */"]);
+        assert_eq!(lex.token().kind, TokenKind::RightBracket);
+        assert_eq!(lex.comments(), ["// d"]);
+        assert_eq!(lex.token().kind, TokenKind::End);
+
+        let mut lex = Lex::with_options("/**/ /* a */\n", options);
+        lex.whitespace_tokens();
+        assert_eq!(lex.token(), Token { span: "/**/ /* a */\n", kind: TokenKind::Whitespace(1) });
+        assert_eq!(lex.comments(), ["/**/", "/* a */"]);
+
+        // Comments end keywords and invalid tokens.
+        let lex = Lex::with_options("[true/*c*/,null// c\n,x/**/]", options);
+        let kinds: Vec<_> = lex.map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBracket,
+            TokenKind::Bool(true),
+            TokenKind::Comma,
+            TokenKind::Null,
+            TokenKind::Comma,
+            TokenKind::Error(LexErrorKind::InvalidToken),
+            TokenKind::RightBracket,
+        ]);
+
+        let error = TokenKind::Error(LexErrorKind::InvalidToken);
+        assert_eq!(Lex::with_options("/* a", options).token(), Token { span: "/*", kind: error.clone() });
+        assert_eq!(Lex::new("// a").token(), Token { span: "//", kind: error });
+    }
+
+    #[test]
+    fn line_separators() {
+        // U+2028 and U+2029 are ordinary characters in JSON strings, in every mode.
+        let s = "\"a\u{2028}b\u{2029}\"";
+        for options in &[Options::strict(), Options::default(), Options::lenient()] {
+            let token = Lex::with_options(s, *options).token();
+            assert_eq!(token, Token { span: s, kind: TokenKind::String(String::from("a\u{2028}b\u{2029}")) });
+        }
+
+        // They are not whitespace between tokens, even with `allow_extra_whitespace`.
+        let tokens: Vec<_> = Lex::with_options("[1,\u{2028}2]", Options::lenient()).collect();
+        assert_eq!(tokens[3], Token { span: "\u{2028}2", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        let error = TokenKind::Error(LexErrorKind::InvalidToken);
+        assert_eq!(Lex::with_options("\u{2029}", Options::lenient()).token().kind, error);
+    }
+
+    #[test]
+    fn error_recovery() {
+        let s = "[@foo, -x] é!x{ 1.e3\"";
+        let mut lex = Lex::new(s);
+        assert_eq!(lex.token(), Token { span: "[", kind: TokenKind::LeftBracket });
+        assert_eq!(lex.token(), Token { span: "@foo", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!(lex.token(), Token { span: ",", kind: TokenKind::Comma });
+        assert_eq!(lex.token(), Token { span: "-x", kind: TokenKind::Error(LexErrorKind::InvalidNumber) });
+        assert_eq!(lex.token(), Token { span: "]", kind: TokenKind::RightBracket });
+        assert_eq!(lex.token(), Token { span: "é!x", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!(lex.token(), Token { span: "{", kind: TokenKind::LeftBrace });
+        assert_eq!(lex.token(), Token { span: "1.e3", kind: TokenKind::Error(LexErrorKind::InvalidNumber) });
+        assert_eq!(lex.token(), Token { span: "\"", kind: TokenKind::Error(LexErrorKind::UnterminatedString) });
+        assert_eq!(lex.token(), Token { span: "", kind: TokenKind::End });
+    }
+
+    #[test]
+    fn try_token() {
+        let s = "[1.5e, \"a\\q\", \"b";
+        let mut lex = Lex::with_options(s, Options::strict());
+        assert_eq!(lex.try_token().map(|token| token.kind), Ok(TokenKind::LeftBracket));
+        let error = lex.try_token().unwrap_err();
+        assert_eq!(error, LexError { kind: LexErrorKind::InvalidNumber, span: "1.5e", offset: 1 });
+        assert_eq!(error.to_string(), "invalid number `1.5e` at offset 1");
+        assert_eq!(lex.try_token().map(|token| token.kind), Ok(TokenKind::Comma));
+        let error = lex.try_token().unwrap_err();
+        assert_eq!(error, LexError { kind: LexErrorKind::InvalidEscape, span: r#""a\q""#, offset: 7 });
+        assert_eq!(lex.try_token().map(|token| token.kind), Ok(TokenKind::Comma));
+        let error = lex.try_token().unwrap_err();
+        assert_eq!(error, LexError { kind: LexErrorKind::UnterminatedString, span: "\"b", offset: 14 });
+        assert_eq!(lex.try_token().map(|token| token.kind), Ok(TokenKind::End));
+    }
+
+    #[test]
+    #[should_panic(expected = "the span is not part of the lexer's input")]
+    fn foreign_span() {
+        let lex = Lex::new("[1]");
+        lex.line_column(&String::from("[1]"));
+    }
+
+    #[test]
+    fn error_spans() {
+        let s = "[\"abc";
+        let mut lex = Lex::new(s);
+        assert_eq!(lex.token().kind, TokenKind::LeftBracket);
+        let token = lex.token();
+        assert_eq!(token, Token { span: "\"abc", kind: TokenKind::Error(LexErrorKind::UnterminatedString) });
+        assert_eq!((lex.offset(token.span), lex.line_column(token.span)), (1, (1, 2)));
+        assert_eq!(lex.token(), Token { span: "", kind: TokenKind::End });
+
+        let s = "{\n  é: @\n}";
+        let mut lex = Lex::new(s);
+        assert_eq!(lex.token().kind, TokenKind::LeftBrace);
+        let token = lex.token();
+        assert_eq!(token, Token { span: "é", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!((lex.offset(token.span), lex.line_column(token.span)), (4, (2, 3)));
+        assert_eq!(lex.token().kind, TokenKind::Colon);
+        let token = lex.token();
+        assert_eq!(token, Token { span: "@", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!((lex.offset(token.span), lex.line_column(token.span)), (8, (2, 6)));
+        assert_eq!(lex.token().kind, TokenKind::RightBrace);
+
+        let s = "[1, -]";
+        let mut lex = Lex::new(s);
+        assert_eq!(lex.token().kind, TokenKind::LeftBracket);
+        assert_eq!(lex.token().kind, TokenKind::Number(1.0));
+        assert_eq!(lex.token().kind, TokenKind::Comma);
+        let token = lex.token();
+        assert_eq!(token, Token { span: "-", kind: TokenKind::Error(LexErrorKind::InvalidNumber) });
+        assert_eq!((lex.offset(token.span), lex.line_column(token.span)), (4, (1, 5)));
+        assert_eq!(lex.token().kind, TokenKind::RightBracket);
+
+        let mut lex = Lex::new("-");
+        assert_eq!(lex.token(), Token { span: "-", kind: TokenKind::Error(LexErrorKind::InvalidNumber) });
+        assert_eq!(lex.position(), 1);
+    }
+
+    #[test]
+    fn keywords() {
+        for s in &["tru", "truex", "nul", "fals", "nullnull", "falsé"] {
+            let error = TokenKind::Error(LexErrorKind::InvalidToken);
+            assert_eq!(Lex::new(s).collect::<Vec<_>>(), vec![Token { span: s, kind: error }]);
+        }
+
+        let kinds: Vec<_> = Lex::new("[true,false]null").map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBracket,
+            TokenKind::Bool(true),
+            TokenKind::Comma,
+            TokenKind::Bool(false),
+            TokenKind::RightBracket,
+            TokenKind::Null,
+        ]);
+    }
+
+    #[test]
+    fn plus_sign() {
+        // A leading `+` is not part of the number grammar, but the whole number is still a single
+        // invalid token rather than an error followed by a number.
+        let error = TokenKind::Error(LexErrorKind::InvalidToken);
+        assert_eq!(Lex::new("+5").collect::<Vec<_>>(), vec![Token { span: "+5", kind: error.clone() }]);
+        assert_eq!(Lex::new("+5.0").collect::<Vec<_>>(), vec![Token { span: "+5.0", kind: error }]);
+
+        let mut lex = Lex::new("[+5, 1]");
+        assert_eq!(lex.token().kind, TokenKind::LeftBracket);
+        assert_eq!(lex.token(), Token { span: "+5", kind: TokenKind::Error(LexErrorKind::InvalidToken) });
+        assert_eq!(lex.token().kind, TokenKind::Comma);
+    }
+
+    #[test]
+    fn unknown_escapes() {
+        let s = r#""a\x20b\q""#;
+        let lenient = Lex::with_options(s, Options::lenient()).token();
+        assert_eq!(lenient.kind, TokenKind::String(String::from(r"a\x20b\q")));
+        let error = TokenKind::Error(LexErrorKind::InvalidEscape);
+        assert_eq!(Lex::with_options(s, Options::strict()).token(), Token { span: s, kind: error });
+
+        // An escaped quote is still known, so it does not end the string.
+        let s = r#""\x\"" 1"#;
+        let mut lex = Lex::with_options(s, Options::lenient());
+        assert_eq!(lex.token().kind, TokenKind::String(String::from(r#"\x""#)));
+        assert_eq!(lex.token().kind, TokenKind::Number(1.0));
+    }
+
+    #[test]
+    fn raw_strings() {
+        let s = r#"{"a\u0062": ["\"c\q", "é"], "d\"#;
+        let mut lex = Lex::new(s);
+        lex.raw_strings();
+        let tokens: Vec<_> = lex.collect();
+        assert_eq!(tokens[1], Token { span: r#""a\u0062""#, kind: TokenKind::RawString });
+        assert_eq!(tokens[4], Token { span: r#""\"c\q""#, kind: TokenKind::RawString });
+        assert_eq!(tokens[6], Token { span: r#""é""#, kind: TokenKind::RawString });
+        let error = TokenKind::Error(LexErrorKind::UnterminatedString);
+        assert_eq!(tokens[9], Token { span: r#""d\"#, kind: error });
+        assert_eq!(tokens.len(), 10);
+    }
+
+    #[test]
+    fn string_spans() {
+        let s = r#"["a\u0062\n", "\/"]"#;
+        let tokens: Vec<_> = Lex::new(s).collect();
+        assert_eq!(tokens[1], Token { span: r#""a\u0062\n""#, kind: TokenKind::String(String::from("ab\n")) });
+        assert_eq!(tokens[3], Token { span: r#""\/""#, kind: TokenKind::String(String::from("/")) });
+    }
+
+    #[test]
+    fn surrogates() {
+        let string = |s: &str| TokenKind::String(String::from(s));
+
+        let s = r#""\uD834\uDD1E""#;
+        assert_eq!(Lex::new(s).token().kind, string("\u{1D11E}"));
+        assert_eq!(Lex::with_options(s, Options::strict()).token().kind, string("\u{1D11E}"));
+
+        for &(s, replaced) in &[
+            (r#""\uD834""#, "\u{FFFD}"),
+            (r#""\uD834\uD834""#, "\u{FFFD}\u{FFFD}"),
+            (r#""\uDD1E""#, "\u{FFFD}"),
+            (r#""\uD834x""#, "\u{FFFD}x"),
+            (r#""\uD834\uD834\uDD1E""#, "\u{FFFD}\u{1D11E}"),
+        ] {
+            assert_eq!(Lex::new(s).token().kind, string(replaced));
+            let error = TokenKind::Error(LexErrorKind::InvalidEscape);
+            assert_eq!(Lex::with_options(s, Options::strict()).token(), Token { span: s, kind: error });
+        }
+    }
+
+    #[test]
+    fn iterator() {
+        let s = "[1, true]";
+        let kinds: Vec<_> = Lex::new(s).map(|token| token.kind).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::LeftBracket,
+            TokenKind::Number(1.0),
+            TokenKind::Comma,
+            TokenKind::Bool(true),
+            TokenKind::RightBracket,
+        ]);
+    }
 }
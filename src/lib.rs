@@ -1,7 +1,23 @@
 #![feature(slice_patterns)]
 
-pub use parse::Parse;
+pub use parse::{Parse, Parser, ParseError, ParseErrorKind, ReadError, Spans, Comments, Types, ArrayElements};
+pub use parse::{validate, validate_with, parse_scalar, decode_string, unescape_json_string};
+#[cfg(feature = "stats")]
+pub use parse::ParseStats;
+pub use options::{Options, NumberMode};
+pub use json::escape_json_string;
+pub use measure::{measure, DocStats};
+pub use format::{format, minify, FormatOptions};
+pub use patch::PatchOp;
+pub use incremental::IncrementalParser;
+pub use seq::{parse_seq, parse_seq_with, Seq};
 
 mod parse;
-pub(crate) mod lex;
+mod options;
+mod measure;
+mod format;
+mod patch;
+mod incremental;
+mod seq;
+pub mod lex;
 pub mod json;
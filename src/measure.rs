@@ -0,0 +1,57 @@
+use lex::{Lex, TokenKind};
+
+/// Statistics about a JSON document, gathered from its tokens without building a value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DocStats {
+    /// The number of tokens, including invalid ones but not the end of input.
+    pub tokens: usize,
+    /// The deepest nesting of objects and arrays.
+    pub max_depth: usize,
+    /// The total length in bytes of all decoded strings, including object keys.
+    pub string_bytes: usize,
+    /// The number of numbers.
+    pub numbers: usize,
+}
+
+/// Measure the complexity of a JSON document.
+///
+/// This only lexes the document, so it is cheaper than parsing it, and it does not check that
+/// the document is well-formed. Unbalanced closing brackets do not reduce the depth below zero.
+pub fn measure(source: &str) -> DocStats {
+    let mut lex = Lex::new(source);
+    let mut stats = DocStats::default();
+    let mut depth = 0;
+    loop {
+        let token = lex.token();
+        match token.kind {
+            TokenKind::End => break,
+
+            TokenKind::LeftBrace | TokenKind::LeftBracket => {
+                depth += 1;
+                if depth > stats.max_depth {
+                    stats.max_depth = depth;
+                }
+            }
+            TokenKind::RightBrace | TokenKind::RightBracket => depth = depth.saturating_sub(1),
+
+            TokenKind::String(ref string) => stats.string_bytes += string.len(),
+            TokenKind::Number(_) | TokenKind::Integer(_) | TokenKind::RawNumber(_) => stats.numbers += 1,
+
+            _ => {}
+        }
+        stats.tokens += 1;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use measure::{measure, DocStats};
+
+    #[test]
+    fn measure_document() {
+        let s = r#"{ "foo": [1, [2, {}]], "bar": "bé" }"#;
+        let stats = measure(s);
+        assert_eq!(stats, DocStats { tokens: 18, max_depth: 4, string_bytes: 9, numbers: 2 });
+    }
+}
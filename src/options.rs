@@ -0,0 +1,117 @@
+/// Configuration for the lexer and parser.
+///
+/// Each field toggles a single behavior. The `strict` and `lenient` presets are starting points,
+/// which can be adjusted with struct update syntax:
+///
+/// ```
+/// # use json_parser::{Options, NumberMode};
+/// let options = Options { number_mode: NumberMode::Raw, ..Options::strict() };
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    /// How to represent numbers in tokens and values.
+    pub number_mode: NumberMode,
+    /// Skip a UTF-8 byte order mark at the start of the input.
+    pub allow_bom: bool,
+    /// Skip a first line that begins with `#`, such as a shebang, before the first token.
+    pub allow_hash_line: bool,
+    /// Treat form feed and vertical tab as whitespace between tokens, in addition to the space,
+    /// tab, carriage return and newline of the JSON grammar.
+    ///
+    /// The Unicode line and paragraph separators U+2028 and U+2029 are never whitespace, though
+    /// they are allowed unescaped in strings.
+    pub allow_extra_whitespace: bool,
+    /// Allow whitespace before the first token and after the last one, rather than requiring the
+    /// input to be exactly a JSON value.
+    pub allow_surrounding_whitespace: bool,
+    /// Skip `//` line comments and `/* */` block comments between tokens, as whitespace.
+    pub allow_comments: bool,
+    /// Replace invalid `\u` escape sequences, such as unpaired surrogates, with U+FFFD,
+    /// rather than rejecting the string that contains them.
+    pub replace_invalid_unicode: bool,
+    /// Keep a backslash followed by a character that does not form an escape sequence, such as
+    /// `\x`, as those two characters, rather than rejecting the string that contains it.
+    pub allow_unknown_escapes: bool,
+    /// Reject integers that cannot be represented exactly as an `f64`, such as those beyond 2^53.
+    /// This only applies to `NumberMode::Float`.
+    pub exact_integers: bool,
+    /// The maximum number of members in any single object.
+    pub max_object_keys: Option<usize>,
+    /// The maximum nesting depth of objects and arrays, where a top-level container is at depth 1.
+    pub max_depth: Option<usize>,
+    /// The maximum total number of array elements and object members in a document, counted
+    /// across every array and object in it.
+    pub max_elements: Option<usize>,
+}
+
+impl Options {
+    /// Accept only the JSON grammar of RFC 7159, with no extensions.
+    pub fn strict() -> Options {
+        Options {
+            number_mode: NumberMode::Float,
+            allow_bom: false,
+            allow_hash_line: false,
+            allow_extra_whitespace: false,
+            allow_surrounding_whitespace: true,
+            allow_comments: false,
+            replace_invalid_unicode: false,
+            allow_unknown_escapes: false,
+            exact_integers: true,
+            max_object_keys: None,
+            max_depth: None,
+            max_elements: None,
+        }
+    }
+
+    /// Accept every supported extension to the JSON grammar.
+    pub fn lenient() -> Options {
+        Options {
+            number_mode: NumberMode::Float,
+            allow_bom: true,
+            allow_hash_line: true,
+            allow_extra_whitespace: true,
+            allow_surrounding_whitespace: true,
+            allow_comments: true,
+            replace_invalid_unicode: true,
+            allow_unknown_escapes: true,
+            exact_integers: false,
+            max_object_keys: None,
+            max_depth: None,
+            max_elements: None,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            number_mode: NumberMode::Float,
+            allow_bom: true,
+            allow_hash_line: false,
+            allow_extra_whitespace: false,
+            allow_surrounding_whitespace: true,
+            allow_comments: false,
+            replace_invalid_unicode: true,
+            allow_unknown_escapes: true,
+            exact_integers: false,
+            max_object_keys: None,
+            max_depth: None,
+            max_elements: None,
+        }
+    }
+}
+
+/// A representation for numbers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NumberMode {
+    /// Convert every number to an `f64`, as `json::Value::Number`.
+    Float,
+    /// Convert integers to an `i64`, as `json::Value::Integer`, rejecting those that overflow.
+    /// Numbers with a fraction or exponent are still converted to an `f64`.
+    Integer,
+    /// Convert integers to an `i64` as with `Integer`, but keep those that overflow as their
+    /// original source text, as `json::Value::RawNumber`, rather than rejecting them.
+    BigInteger,
+    /// Keep numbers as their original source text, as `json::Value::RawNumber`.
+    Raw,
+}
@@ -1,5 +1,11 @@
-use std::{fmt, result};
-use lex::{Lex, Token, TokenKind};
+use std::{error, fmt, io, mem, result};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::ops::Range;
+use std::rc::Rc;
+use lex::{Lex, Token, TokenKind, LexErrorKind};
+use options::Options;
 use json;
 
 /// A JSON parser.
@@ -12,51 +18,624 @@ use json;
 ///
 /// array = '[' ']' | '[' elements ']'
 /// elements = value | elements ',' value
+///
+/// document = object | array
+///
+/// stream = '[' ']' | '[' elements ']'
+///
+/// values = | value values
 pub struct Parse<'source> {
     lex: Lex<'source>,
+    options: Options,
+    depth: usize,
+    /// The number of array elements and object members started so far, for `Options::max_elements`.
+    elements: usize,
+    /// The deepest `depth` reached so far, for `ParseStats`.
+    #[cfg(feature = "stats")]
+    max_depth: usize,
+    merge_keys: Option<MergeKeys<'source>>,
+    /// The capacity to start a top-level array with.
+    array_capacity: usize,
+    /// The capacity to start a top-level object with.
+    object_capacity: usize,
+
+    spans: Option<Spans>,
+    comments: Option<Comments>,
+    types: Option<Types>,
+    path: Vec<Segment>,
+}
+
+/// A step in the path to the current value.
+enum Segment {
+    /// An object key, escaped for a JSON Pointer.
+    Key(String),
+    Index(usize),
 }
 
 pub type Result<'source, T> = result::Result<T, ParseError<'source>>;
 
-/// An unexpected token.
+/// A callback that combines the existing and new values of a repeated object key.
+type MergeKeys<'source> = Box<dyn FnMut(&str, json::Value, json::Value) -> json::Value + 'source>;
+
+/// The byte range of each value in the source, keyed by JSON Pointer.
+pub type Spans = HashMap<String, Range<usize>>;
+
+/// Statistics about a parse, from `Parse::value_with_stats`.
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ParseStats {
+    /// The number of tokens the parser read, not including the end of input.
+    pub tokens: usize,
+    /// The deepest nesting of objects and arrays.
+    pub max_depth: usize,
+}
+
+/// The comments before each value in the source, with their delimiters, keyed by JSON Pointer.
+pub type Comments = HashMap<String, Vec<String>>;
+
+/// The kinds of the values in the source, keyed by JSON Pointer with `*` in place of array
+/// indices.
+pub type Types = HashMap<String, HashSet<json::ValueKind>>;
+
+/// An error at a particular token.
 pub struct ParseError<'source> {
     token: Token<'source>,
+    kind: ParseErrorKind,
+    state: &'static str,
+}
+
+/// The reason for a `ParseError`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    /// The token is not allowed at this point in the grammar.
+    UnexpectedToken,
+    /// The token is a `:` where a value was expected, as in `{"a"::1}` or `[:]`.
+    UnexpectedColon,
+    /// The token is a key that would give an object more than `Options::max_object_keys` members.
+    TooManyKeys,
+    /// The token opens an object or array nested more than `Options::max_depth` deep.
+    TooDeep,
+    /// The token starts an array element or object member that would give the document more
+    /// than `Options::max_elements` of them.
+    LimitExceeded,
+}
+
+impl<'source> ParseError<'source> {
+    fn new(token: Token<'source>, kind: ParseErrorKind, state: &'static str) -> Self {
+        ParseError { token, kind, state }
+    }
+
+    fn unexpected(token: Token<'source>, state: &'static str) -> Self {
+        Self::new(token, ParseErrorKind::UnexpectedToken, state)
+    }
+
+    /// The reason for this error.
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    /// The source text of the token that caused this error.
+    pub fn span(&self) -> &'source str {
+        self.token.span
+    }
+
+    /// The reason the lexer rejected the token that caused this error, if it was invalid rather
+    /// than only out of place.
+    ///
+    /// Such errors are always `ParseErrorKind::UnexpectedToken`.
+    pub fn lex_error(&self) -> Option<LexErrorKind> {
+        match self.token.kind {
+            TokenKind::Error(kind) => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// Render this error as a multi-line diagnostic, with the line of `source` where it
+    /// occurred and a caret under the offending token:
+    ///
+    /// ```text
+    /// error: unexpected token `]`
+    ///  --> 2:8
+    ///   |
+    /// 2 |   "a": ],
+    ///   |        ^
+    /// ```
+    ///
+    /// The source must be the input this error was produced from. Otherwise, only the first
+    /// line of the diagnostic is rendered.
+    pub fn render(&self, source: &str) -> String {
+        let span = self.token.span;
+        let message = describe(self.kind, self.lex_error(), span);
+        let lex = Lex::new(source);
+        let offset = match lex.try_offset(span) {
+            Some(offset) if source.is_char_boundary(offset) => offset,
+            _ => return format!("error: {}\n", message),
+        };
+        let (line, column) = lex.line_column(span);
+        let start = source[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        let end = source[offset..].find('\n').map_or(source.len(), |newline| offset + newline);
+        let text = source[start..end].trim_end_matches('\r');
+
+        // Keep tabs in the indentation, so the caret lines up with the token.
+        let indent: String = source[start..offset].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let carets = span.lines().next().map_or(0, |span| span.chars().count()).max(1);
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "error: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            message, gutter, line, column, gutter, line, text, gutter, indent, "^".repeat(carets),
+        )
+    }
+
+    /// The parser state where this error occurred, such as `S10 (object_open_pairs)`.
+    ///
+    /// This is meant for debugging the grammar, and is not stable.
+    pub fn state(&self) -> &'static str {
+        self.state
+    }
 }
 
 impl<'source> fmt::Debug for ParseError<'source> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "unexpected token {:?}", self.token)?;
-        Ok(())
+        let message = describe(self.kind, self.lex_error(), self.token.span);
+        write!(f, "{} at {:?} in state {}", message, self.token, self.state)
+    }
+}
+
+/// Describe an error of the given kind at a token, such as "unexpected token `]`", or "invalid
+/// number `1.e3`" if the lexer rejected the token.
+pub(crate) fn describe(kind: ParseErrorKind, lex_error: Option<LexErrorKind>, span: &str) -> String {
+    match kind {
+        ParseErrorKind::UnexpectedToken if span.is_empty() => String::from("unexpected end of input"),
+        ParseErrorKind::UnexpectedToken if lex_error.is_some() => {
+            format!("{} `{}`", lex_error.unwrap().reason(), span)
+        }
+        ParseErrorKind::UnexpectedToken => format!("unexpected token `{}`", span),
+        ParseErrorKind::UnexpectedColon => String::from("unexpected `:` where a value was expected"),
+        ParseErrorKind::TooManyKeys => String::from("too many keys in object"),
+        ParseErrorKind::TooDeep => String::from("nesting too deep"),
+        ParseErrorKind::LimitExceeded => String::from("too many elements in document"),
+    }
+}
+
+impl<'source> From<ParseError<'source>> for json::FromStrError {
+    fn from(error: ParseError<'source>) -> Self {
+        let lex_error = error.lex_error();
+        json::FromStrError { kind: error.kind, lex_error, span: String::from(error.span()) }
+    }
+}
+
+/// An error from `Parse::from_reader`.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading failed, or the input was not valid UTF-8.
+    Io(io::Error),
+    Parse(json::FromStrError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref error) => write!(f, "{}", error),
+            ReadError::Parse(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
     }
 }
 
-struct Value(json::Value);
-struct Object(json::Object);
-struct Pairs(json::Object);
-struct Pair((String, json::Value));
-struct Array(json::Array);
-struct Elements(json::Array);
+struct Value<V>(V);
+struct Object<O>(O);
+struct Pairs<O>(O, usize);
+struct Pair<K, V>((K, V));
+struct Array<A>(A);
+struct Elements<A>(A, usize);
+
+/// An iterator over the elements of a top-level array, created by `Parse::array_elements`.
+pub struct ArrayElements<'parse, 'source: 'parse> {
+    parse: &'parse mut Parse<'source>,
+    state: StreamState,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum StreamState {
+    Start,
+    Elements,
+    Done,
+}
 
 enum Either<T, U> {
     Left(T),
     Right(U),
 }
 
+/// Either more pairs after a comma, or the finished object.
+type MorePairs<O> = Either<Pairs<O>, Object<O>>;
+/// Either more elements after a comma, or the finished array.
+type MoreElements<A> = Either<Elements<A>, Array<A>>;
+
+/// The values produced by reducing each grammar rule.
+///
+/// The parser's states are generic over this, so the same grammar can build different
+/// representations of a value.
+trait Build<'source> {
+    type Value;
+    type Key;
+    type Object;
+    type Array;
+
+    fn string(&mut self, span: &'source str, string: String) -> Self::Value;
+    fn number(&mut self, number: f64) -> Self::Value;
+    fn integer(&mut self, integer: i64) -> Self::Value;
+    fn raw_number(&mut self, number: String) -> Self::Value;
+    fn bool(&mut self, bool_: bool) -> Self::Value;
+    fn null(&mut self) -> Self::Value;
+
+    fn key(&mut self, span: &'source str, string: String) -> Self::Key;
+    /// Start an object, with room for `capacity` members.
+    fn object(&mut self, capacity: usize) -> Self::Object;
+    fn insert(&mut self, object: &mut Self::Object, key: Self::Key, value: Self::Value);
+    fn object_value(&mut self, object: Self::Object) -> Self::Value;
+
+    /// Start an array, with room for `capacity` elements.
+    fn array(&mut self, capacity: usize) -> Self::Array;
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value);
+    fn array_value(&mut self, array: Self::Array) -> Self::Value;
+}
+
+/// Build a `json::Value`.
+struct BuildValue<'source> {
+    merge_keys: Option<MergeKeys<'source>>,
+}
+
+impl<'source> Build<'source> for BuildValue<'source> {
+    type Value = json::Value;
+    type Key = String;
+    type Object = json::Object;
+    type Array = json::Array;
+
+    fn string(&mut self, _: &'source str, string: String) -> json::Value { json::Value::String(string) }
+    fn number(&mut self, number: f64) -> json::Value { json::Value::Number(number) }
+    fn integer(&mut self, integer: i64) -> json::Value { json::Value::Integer(integer) }
+    fn raw_number(&mut self, number: String) -> json::Value { json::Value::RawNumber(number) }
+    fn bool(&mut self, bool_: bool) -> json::Value { json::Value::Bool(bool_) }
+    fn null(&mut self) -> json::Value { json::Value::Null }
+
+    fn key(&mut self, _: &'source str, string: String) -> String { string }
+    fn object(&mut self, capacity: usize) -> json::Object { json::Object::with_capacity(capacity) }
+    fn insert(&mut self, object: &mut json::Object, key: String, value: json::Value) {
+        match (object.entry(key), self.merge_keys.as_mut()) {
+            (Entry::Occupied(mut entry), Some(merge_keys)) => {
+                let existing = entry.get_mut().take();
+                let merged = merge_keys(entry.key(), existing, value);
+                entry.insert(merged);
+            }
+            (Entry::Occupied(mut entry), None) => { entry.insert(value); }
+            (Entry::Vacant(entry), _) => { entry.insert(value); }
+        }
+    }
+    fn object_value(&mut self, object: json::Object) -> json::Value { json::Value::Object(object) }
+
+    fn array(&mut self, capacity: usize) -> json::Array { json::Array::with_capacity(capacity) }
+    fn push(&mut self, array: &mut json::Array, value: json::Value) { array.push(value); }
+    fn array_value(&mut self, array: json::Array) -> json::Value { json::Value::Array(array) }
+}
+
+/// Build nothing, for only validating a value.
+struct BuildNothing;
+
+impl<'source> Build<'source> for BuildNothing {
+    type Value = ();
+    type Key = ();
+    type Object = ();
+    type Array = ();
+
+    fn string(&mut self, _: &'source str, _: String) {}
+    fn number(&mut self, _: f64) {}
+    fn integer(&mut self, _: i64) {}
+    fn raw_number(&mut self, _: String) {}
+    fn bool(&mut self, _: bool) {}
+    fn null(&mut self) {}
+
+    fn key(&mut self, _: &'source str, _: String) {}
+    fn object(&mut self, _: usize) {}
+    fn insert(&mut self, _: &mut (), _: (), _: ()) {}
+    fn object_value(&mut self, _: ()) {}
+
+    fn array(&mut self, _: usize) {}
+    fn push(&mut self, _: &mut (), _: ()) {}
+    fn array_value(&mut self, _: ()) {}
+}
+
+/// Build a `json::BorrowedValue`, from a lexer that only decodes strings with escape sequences.
+struct BuildBorrowedValue;
+
+impl<'source> BuildBorrowedValue {
+    /// Borrow a string's contents from its span, unless it had to be decoded.
+    fn borrow(span: &'source str, string: String) -> Cow<'source, str> {
+        if span.contains('\\') {
+            Cow::Owned(string)
+        } else {
+            Cow::Borrowed(&span[1..span.len() - 1])
+        }
+    }
+}
+
+impl<'source> Build<'source> for BuildBorrowedValue {
+    type Value = json::BorrowedValue<'source>;
+    type Key = Cow<'source, str>;
+    type Object = json::BorrowedObject<'source>;
+    type Array = json::BorrowedArray<'source>;
+
+    fn string(&mut self, span: &'source str, string: String) -> Self::Value {
+        json::BorrowedValue::String(Self::borrow(span, string))
+    }
+    fn number(&mut self, number: f64) -> Self::Value { json::BorrowedValue::Number(number) }
+    fn integer(&mut self, integer: i64) -> Self::Value { json::BorrowedValue::Integer(integer) }
+    fn raw_number(&mut self, number: String) -> Self::Value { json::BorrowedValue::RawNumber(number) }
+    fn bool(&mut self, bool_: bool) -> Self::Value { json::BorrowedValue::Bool(bool_) }
+    fn null(&mut self) -> Self::Value { json::BorrowedValue::Null }
+
+    fn key(&mut self, span: &'source str, string: String) -> Self::Key { Self::borrow(span, string) }
+    fn object(&mut self, capacity: usize) -> Self::Object { json::BorrowedObject::with_capacity(capacity) }
+    fn insert(&mut self, object: &mut Self::Object, key: Self::Key, value: Self::Value) {
+        object.insert(key, value);
+    }
+    fn object_value(&mut self, object: Self::Object) -> Self::Value { json::BorrowedValue::Object(object) }
+
+    fn array(&mut self, capacity: usize) -> Self::Array { json::BorrowedArray::with_capacity(capacity) }
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value) { array.push(value); }
+    fn array_value(&mut self, array: Self::Array) -> Self::Value { json::BorrowedValue::Array(array) }
+}
+
+/// Build a `json::InternedValue`, sharing repeated object keys.
+struct BuildInternedValue {
+    keys: HashSet<Rc<str>>,
+}
+
+impl<'source> Build<'source> for BuildInternedValue {
+    type Value = json::InternedValue;
+    type Key = Rc<str>;
+    type Object = json::InternedObject;
+    type Array = json::InternedArray;
+
+    fn string(&mut self, _: &'source str, string: String) -> Self::Value { json::InternedValue::String(string) }
+    fn number(&mut self, number: f64) -> Self::Value { json::InternedValue::Number(number) }
+    fn integer(&mut self, integer: i64) -> Self::Value { json::InternedValue::Integer(integer) }
+    fn raw_number(&mut self, number: String) -> Self::Value { json::InternedValue::RawNumber(number) }
+    fn bool(&mut self, bool_: bool) -> Self::Value { json::InternedValue::Bool(bool_) }
+    fn null(&mut self) -> Self::Value { json::InternedValue::Null }
+
+    fn key(&mut self, _: &'source str, string: String) -> Self::Key {
+        if let Some(key) = self.keys.get(&*string) {
+            return key.clone();
+        }
+        let key: Rc<str> = Rc::from(string);
+        self.keys.insert(key.clone());
+        key
+    }
+    fn object(&mut self, capacity: usize) -> Self::Object { json::InternedObject::with_capacity(capacity) }
+    fn insert(&mut self, object: &mut Self::Object, key: Self::Key, value: Self::Value) {
+        object.insert(key, value);
+    }
+    fn object_value(&mut self, object: Self::Object) -> Self::Value { json::InternedValue::Object(object) }
+
+    fn array(&mut self, capacity: usize) -> Self::Array { json::InternedArray::with_capacity(capacity) }
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value) { array.push(value); }
+    fn array_value(&mut self, array: Self::Array) -> Self::Value { json::InternedValue::Array(array) }
+}
+
 impl<'source> Parse<'source> {
     /// Create a new JSON parser for the given string.
     ///
     /// The entire string should consist of a single JSON value.
     pub fn new(source: &'source str) -> Self {
-        let lex = Lex::new(source);
-        Parse { lex }
+        Self::with_options(source, Options::default())
+    }
+
+    /// Create a new JSON parser for the given string, with the given options.
+    pub fn with_options(source: &'source str, options: Options) -> Self {
+        Self::with_lex(Lex::with_options(source, options), options)
+    }
+
+    fn with_lex(lex: Lex<'source>, options: Options) -> Self {
+        Parse {
+            lex,
+            options,
+            depth: 0,
+            elements: 0,
+            #[cfg(feature = "stats")]
+            max_depth: 0,
+            merge_keys: None,
+            array_capacity: 0,
+            object_capacity: 0,
+            spans: None,
+            comments: None,
+            types: None,
+            path: Vec::new(),
+        }
+    }
+
+    /// Combine the values of repeated object keys with a callback, rather than keeping only
+    /// the last value.
+    ///
+    /// The callback receives the key, the value so far, and the new value, and returns the
+    /// value to keep. This only applies to `json::Value`s.
+    pub fn merge_duplicate_keys<F>(mut self, merge_keys: F) -> Self
+        where F: FnMut(&str, json::Value, json::Value) -> json::Value + 'source
+    {
+        self.merge_keys = Some(Box::new(merge_keys));
+        self
+    }
+
+    /// Preallocate room for `capacity` elements in a top-level array, rather than growing it as
+    /// elements are parsed.
+    ///
+    /// This is only a hint, so an array with more elements still grows as usual. Nested arrays are
+    /// not affected.
+    pub fn array_capacity(mut self, capacity: usize) -> Self {
+        self.array_capacity = capacity;
+        self
+    }
+
+    /// Preallocate room for `capacity` members in a top-level object, rather than growing it as
+    /// members are parsed.
+    ///
+    /// This is only a hint, so an object with more members still grows as usual. Nested objects
+    /// are not affected.
+    pub fn object_capacity(mut self, capacity: usize) -> Self {
+        self.object_capacity = capacity;
+        self
     }
 
     /// Parse a JSON value.
     pub fn value(&mut self) -> Result<'source, json::Value> {
-        let Value(value) = self.goal_start()?;
+        let Value(value) = self.build_value(|parse, build| parse.goal_start(build))?;
+        Ok(value)
+    }
+
+    /// Read all of `reader` and parse it as a JSON value.
+    ///
+    /// The whole input is read before parsing, so this needs as much memory as parsing a string.
+    pub fn from_reader<R: io::Read>(mut reader: R) -> result::Result<json::Value, ReadError> {
+        let mut source = String::new();
+        reader.read_to_string(&mut source)?;
+        source.parse().map_err(ReadError::Parse)
+    }
+
+    /// Parse a JSON value, borrowing its strings and object keys from the source.
+    ///
+    /// Only strings with escape sequences are decoded into new allocations.
+    pub fn borrowed_value(&mut self) -> Result<'source, json::BorrowedValue<'source>> {
+        self.lex.borrow_strings();
+        let Value(value) = self.goal_start(&mut BuildBorrowedValue)?;
+        Ok(value)
+    }
+
+    /// Parse a JSON value, sharing one allocation between all object keys with the same text.
+    pub fn interned_value(&mut self) -> Result<'source, json::InternedValue> {
+        let Value(value) = self.goal_start(&mut BuildInternedValue { keys: HashSet::new() })?;
+        Ok(value)
+    }
+
+    /// Check that the source is a single valid JSON value, without building it.
+    ///
+    /// Strings without escape sequences are not decoded, so this allocates very little.
+    pub fn validate(&mut self) -> Result<'source, ()> {
+        self.lex.borrow_strings();
+        let Value(value) = self.goal_start(&mut BuildNothing)?;
+        Ok(value)
+    }
+
+    /// Parse a JSON document, which must be an object or an array.
+    ///
+    /// This enforces the older top-level rule of RFC 4627, which does not allow bare scalars.
+    pub fn document(&mut self) -> Result<'source, json::Value> {
+        let Value(value) = self.build_value(|parse, build| parse.document_start(build))?;
         Ok(value)
     }
 
+    /// Parse a top-level JSON array one element at a time.
+    ///
+    /// Each element is parsed as the iterator reaches it, so the whole array is never held in
+    /// memory at once. The iterator ends after the closing bracket and the end of the input,
+    /// or after yielding the first error.
+    pub fn array_elements<'parse>(&'parse mut self) -> ArrayElements<'parse, 'source> {
+        ArrayElements { parse: self, state: StreamState::Start }
+    }
+
+    /// Parse a JSON value at the start of the source, without requiring the end of the input
+    /// after it.
+    ///
+    /// The parser stops right after the value, and `remaining` returns the rest of the source.
+    pub fn value_prefix(&mut self) -> Result<'source, json::Value> {
+        match self.build_value(|parse, build| parse.values_start(build))? {
+            Some(value) => Ok(value),
+            None => {
+                let token = Token { span: self.lex.remaining(), kind: TokenKind::End };
+                Err(ParseError::unexpected(token, "S30 (values_start)"))
+            }
+        }
+    }
+
+    /// The source after the last token the parser read.
+    pub fn remaining(&self) -> &'source str {
+        self.lex.remaining()
+    }
+
+    /// The number of bytes of the source the parser has read.
+    ///
+    /// After `value_prefix`, this is the byte offset just past the value. After `value`, which
+    /// reads to the end of the input, it is the length of the source.
+    pub fn consumed(&self) -> usize {
+        self.lex.position()
+    }
+
+    /// Parse a sequence of JSON values, such as concatenated or newline-delimited values, and
+    /// call `f` on each one as soon as it is complete.
+    ///
+    /// Parsing stops at the end of the input, or at the first error, which is returned.
+    pub fn for_each_value<F: FnMut(json::Value)>(&mut self, mut f: F) -> Result<'source, ()> {
+        while let Some(value) = self.build_value(|parse, build| parse.values_start(build))? {
+            f(value);
+        }
+        Ok(())
+    }
+
+    /// Parse a JSON value, along with the source span of it and every nested value.
+    ///
+    /// The root value's span is keyed by the empty pointer `""`.
+    pub fn value_with_spans(&mut self) -> Result<'source, (json::Value, Spans)> {
+        self.spans = Some(Spans::new());
+        let Value(value) = self.build_value(|parse, build| parse.goal_start(build))?;
+        let spans = self.spans.take().unwrap_or_default();
+        Ok((value, spans))
+    }
+
+    /// Parse a JSON value, along with the comments before it and every nested value, so they
+    /// can be written back out with `json::Value::write_with_comments`.
+    ///
+    /// Comments are only recognized with `Options::allow_comments`. Those before an object
+    /// member's key or its value are both attached to the member. Comments anywhere else, such
+    /// as before a comma or a closing bracket, are dropped.
+    pub fn value_with_comments(&mut self) -> Result<'source, (json::Value, Comments)> {
+        self.comments = Some(Comments::new());
+        let Value(value) = self.build_value(|parse, build| parse.goal_start(build))?;
+        let comments = self.comments.take().unwrap_or_default();
+        Ok((value, comments))
+    }
+
+    /// Parse a JSON value, along with the kind of it and every nested value.
+    ///
+    /// This is meant for inferring a schema by merging the kinds from many documents. Array
+    /// elements share the wildcard segment `*`, so the kinds of every element of `/a` are
+    /// collected together under `/a/*`. An object key `*` also uses that segment.
+    pub fn value_collecting_types(&mut self) -> Result<'source, (json::Value, Types)> {
+        self.types = Some(Types::new());
+        let Value(value) = self.build_value(|parse, build| parse.goal_start(build))?;
+        let types = self.types.take().unwrap_or_default();
+        Ok((value, types))
+    }
+
+    /// Parse a JSON value, along with statistics about the parse.
+    ///
+    /// This is only available with the `stats` feature, so that parses without it do no extra
+    /// work to gather them.
+    #[cfg(feature = "stats")]
+    pub fn value_with_stats(&mut self) -> Result<'source, (json::Value, ParseStats)> {
+        let value = self.value()?;
+        let stats = ParseStats { tokens: self.lex.tokens(), max_depth: self.max_depth };
+        Ok((value, stats))
+    }
+
     /// S0 = value = * STRING
     ///      value = * NUMBER
     ///      value = * BOOL
@@ -67,55 +646,121 @@ impl<'source> Parse<'source> {
     ///      object = * '{' '}'
     ///      array = * '[' elements ']'
     ///      array = * '[' ']'
-    fn goal_start(&mut self) -> Result<'source, Value> {
+    fn goal_start<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Value<B::Value>> {
+        let rest = self.lex.remaining();
+        let token = self.lex.token();
+        self.check_edge_whitespace(rest, token.span, "S0 (goal_start)")?;
+        let start = self.lex.offset(token.span);
+        self.record_type(&token.kind);
+        self.record_comments(None);
+        let value = match token {
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S0 (goal_start)"));
+            }
+            Token { kind: TokenKind::LeftBrace, .. } => {
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
+            }
+            Token { kind: TokenKind::LeftBracket, .. } => {
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
+            }
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S0 (goal_start)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S0 (goal_start)")),
+        };
+        self.record_span(start);
+        Ok(self.goal_value(value)?)
+    }
+
+    /// S24= document = * object
+    ///      document = * array
+    ///      object = * '{' pairs '}'
+    ///      object = * '{' '}'
+    ///      array = * '[' elements ']'
+    ///      array = * '[' ']'
+    fn document_start<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Value<B::Value>> {
+        let rest = self.lex.remaining();
         let token = self.lex.token();
+        self.check_edge_whitespace(rest, token.span, "S24 (document_start)")?;
+        let start = self.lex.offset(token.span);
+        self.record_type(&token.kind);
+        self.record_comments(None);
         let value = match token {
-            Token { kind: TokenKind::String(string), .. } => self.value_string(string)?,
-            Token { kind: TokenKind::Number(number), .. } => self.value_number(number)?,
-            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(bool_)?,
-            Token { kind: TokenKind::Null, .. } => self.value_null()?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S24 (document_start)"));
+            }
             Token { kind: TokenKind::LeftBrace, .. } => {
-                let object = self.object_open()?;
-                self.value_object(object)?
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
             }
             Token { kind: TokenKind::LeftBracket, .. } => {
-                let array = self.array_open()?;
-                self.value_array(array)?
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
             }
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S24 (document_start)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S24 (document_start)")),
         };
+        self.record_span(start);
         Ok(self.goal_value(value)?)
     }
 
-    fn goal_value(&mut self, value: Value) -> Result<'source, Value> {
+    fn goal_value<V>(&mut self, value: Value<V>) -> Result<'source, Value<V>> {
+        let rest = self.lex.remaining();
         let token = self.lex.token();
+        self.check_edge_whitespace(rest, token.span, "accept (goal_value)")?;
         match token {
             Token { kind: TokenKind::End, .. } => Ok(value),
-            _ => return Err(ParseError { token }),
+            _ => return Err(ParseError::unexpected(token, "accept (goal_value)")),
         }
     }
 
     /// S1 = value = STRING *
-    fn value_string(&mut self, string: String) -> Result<'source, Value> {
-        let value = json::Value::String(string);
+    fn value_string<B: Build<'source>>(&mut self, build: &mut B, span: &'source str, string: String) -> Result<'source, Value<B::Value>> {
+        let value = build.string(span, string);
         Ok(Value(value))
     }
 
     /// S2 = value = NUMBER *
-    fn value_number(&mut self, number: f64) -> Result<'source, Value> {
-        let value = json::Value::Number(number);
+    fn value_number<B: Build<'source>>(&mut self, build: &mut B, number: f64) -> Result<'source, Value<B::Value>> {
+        let value = build.number(number);
+        Ok(Value(value))
+    }
+
+    /// S2 = value = NUMBER *, for integers
+    fn value_integer<B: Build<'source>>(&mut self, build: &mut B, integer: i64) -> Result<'source, Value<B::Value>> {
+        let value = build.integer(integer);
+        Ok(Value(value))
+    }
+
+    /// S2 = value = NUMBER *, for numbers kept as source text
+    fn value_raw_number<B: Build<'source>>(&mut self, build: &mut B, number: String) -> Result<'source, Value<B::Value>> {
+        let value = build.raw_number(number);
         Ok(Value(value))
     }
 
     /// S3 = value = BOOL *
-    fn value_bool(&mut self, bool_: bool) -> Result<'source, Value> {
-        let value = json::Value::Bool(bool_);
+    fn value_bool<B: Build<'source>>(&mut self, build: &mut B, bool_: bool) -> Result<'source, Value<B::Value>> {
+        let value = build.bool(bool_);
         Ok(Value(value))
     }
 
     /// S4 = value = NULL *
-    fn value_null(&mut self) -> Result<'source, Value> {
-        let value = json::Value::Null;
+    fn value_null<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Value<B::Value>> {
+        let value = build.null();
         Ok(Value(value))
     }
 
@@ -124,30 +769,46 @@ impl<'source> Parse<'source> {
     ///      pairs = * pair
     ///      pairs = * pairs ',' pair
     ///      pair = * STRING ':' value
-    fn object_open(&mut self) -> Result<'source, Object> {
+    fn object_open<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Object<B::Object>> {
+        self.depth += 1;
+        self.record_depth();
         let token = self.lex.token();
         let mut pairs = match token {
-            Token { kind: TokenKind::String(string), .. } => {
-                let pair = self.pair_string(string)?;
-                self.pairs_pair(pair)?
+            Token { kind: TokenKind::String(_), .. } if self.key_limit_reached(0) => {
+                return Err(ParseError::new(token, ParseErrorKind::TooManyKeys, "S5 (object_open)"));
+            }
+            Token { kind: TokenKind::String(_), .. } if self.element_limit_reached() => {
+                return Err(ParseError::new(token, ParseErrorKind::LimitExceeded, "S5 (object_open)"));
             }
-            Token { kind: TokenKind::RightBrace, .. } => return Ok(self.object_open_close()?),
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::String(string), span } => {
+                self.elements += 1;
+                self.record_comments(Some(&string));
+                let pair = self.pair_string(build, span, string)?;
+                self.pairs_pair(build, pair)?
+            }
+            Token { kind: TokenKind::RightBrace, .. } => {
+                self.depth -= 1;
+                return Ok(self.object_open_close(build)?);
+            }
+            _ => return Err(ParseError::unexpected(token, "S5 (object_open)")),
         };
         loop {
-            match self.object_open_pairs(pairs)? {
+            match self.object_open_pairs(build, pairs)? {
                 Either::Left(p) => pairs = p,
-                Either::Right(object) => return Ok(object),
+                Either::Right(object) => {
+                    self.depth -= 1;
+                    return Ok(object);
+                }
             }
         }
     }
 
     /// S6 = pair = STRING * ':' value
-    fn pair_string(&mut self, string: String) -> Result<'source, Pair> {
+    fn pair_string<B: Build<'source>>(&mut self, build: &mut B, span: &'source str, string: String) -> Result<'source, Pair<B::Key, B::Value>> {
         let token = self.lex.token();
         match token {
-            Token { kind: TokenKind::Colon, .. } => Ok(self.pair_string_colon(string)?),
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::Colon, .. } => Ok(self.pair_string_colon(build, span, string)?),
+            _ => return Err(ParseError::unexpected(token, "S6 (pair_string)")),
         }
     }
 
@@ -162,93 +823,121 @@ impl<'source> Parse<'source> {
     ///      object = * '{' '}'
     ///      array = * '[' elements ']'
     ///      array = * '[' ']'
-    fn pair_string_colon(&mut self, string: String) -> Result<'source, Pair> {
+    fn pair_string_colon<B: Build<'source>>(&mut self, build: &mut B, span: &'source str, string: String) -> Result<'source, Pair<B::Key, B::Value>> {
+        self.push_path(|| Segment::Key(json::escape_segment(&string)));
         let token = self.lex.token();
+        let start = self.lex.offset(token.span);
+        self.record_type(&token.kind);
+        self.record_comments(None);
         let value = match token {
-            Token { kind: TokenKind::String(string), .. } => self.value_string(string)?,
-            Token { kind: TokenKind::Number(number), .. } => self.value_number(number)?,
-            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(bool_)?,
-            Token { kind: TokenKind::Null, .. } => self.value_null()?,
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S7 (pair_string_colon)"));
+            }
             Token { kind: TokenKind::LeftBrace, .. } => {
-                let object = self.object_open()?;
-                self.value_object(object)?
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
             }
             Token { kind: TokenKind::LeftBracket, .. } => {
-                let array = self.array_open()?;
-                self.value_array(array)?
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
             }
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S7 (pair_string_colon)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S7 (pair_string_colon)")),
         };
-        Ok(self.pair_string_colon_value(string, value)?)
+        self.record_span(start);
+        self.path.pop();
+        Ok(self.pair_string_colon_value(build, span, string, value)?)
     }
 
     /// S8 = pair = STRING ':' value *
-    fn pair_string_colon_value(&mut self, string: String, value: Value) -> Result<'source, Pair> {
+    fn pair_string_colon_value<B: Build<'source>>(&mut self, build: &mut B, span: &'source str, string: String, value: Value<B::Value>) -> Result<'source, Pair<B::Key, B::Value>> {
+        let key = build.key(span, string);
         let Value(value) = value;
-        let pair = (string, value);
+        let pair = (key, value);
         Ok(Pair(pair))
     }
 
     /// S9 = pairs = pair *
-    fn pairs_pair(&mut self, pair: Pair) -> Result<'source, Pairs> {
+    fn pairs_pair<B: Build<'source>>(&mut self, build: &mut B, pair: Pair<B::Key, B::Value>) -> Result<'source, Pairs<B::Object>> {
         let Pair((key, value)) = pair;
-        let mut object = json::Object::new();
-        object.insert(key, value);
-        Ok(Pairs(object))
+        let capacity = self.capacity_hint(self.object_capacity);
+        let mut object = build.object(capacity);
+        build.insert(&mut object, key, value);
+        Ok(Pairs(object, 1))
     }
 
     /// S10= object = '{' pairs * '}'
     ///      pairs = pairs * ',' pair
-    fn object_open_pairs(&mut self, pairs: Pairs) -> Result<'source, Either<Pairs, Object>> {
+    fn object_open_pairs<B: Build<'source>>(&mut self, build: &mut B, pairs: Pairs<B::Object>) -> Result<'source, MorePairs<B::Object>> {
         let token = self.lex.token();
         match token {
             Token { kind: TokenKind::Comma, .. } => {
-                let pairs = self.pairs_pairs_comma(pairs)?;
+                let pairs = self.pairs_pairs_comma(build, pairs)?;
                 Ok(Either::Left(pairs))
             }
             Token { kind: TokenKind::RightBrace, .. } => {
                 let object = self.object_open_pairs_close(pairs)?;
                 Ok(Either::Right(object))
             }
-            _ => return Err(ParseError { token }),
+            _ => return Err(ParseError::unexpected(token, "S10 (object_open_pairs)")),
         }
     }
 
     /// S11= pairs = pairs ',' * pair
     ///      pair = * STRING ':' value
-    fn pairs_pairs_comma(&mut self, pairs: Pairs) -> Result<'source, Pairs> {
+    fn pairs_pairs_comma<B: Build<'source>>(&mut self, build: &mut B, pairs: Pairs<B::Object>) -> Result<'source, Pairs<B::Object>> {
         let token = self.lex.token();
         let pair = match token {
-            Token { kind: TokenKind::String(string), .. } => self.pair_string(string)?,
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::String(_), .. } if self.key_limit_reached(pairs.1) => {
+                return Err(ParseError::new(token, ParseErrorKind::TooManyKeys, "S11 (pairs_pairs_comma)"));
+            }
+            Token { kind: TokenKind::String(_), .. } if self.element_limit_reached() => {
+                return Err(ParseError::new(token, ParseErrorKind::LimitExceeded, "S11 (pairs_pairs_comma)"));
+            }
+            Token { kind: TokenKind::String(string), span } => {
+                self.elements += 1;
+                self.record_comments(Some(&string));
+                self.pair_string(build, span, string)?
+            }
+            _ => return Err(ParseError::unexpected(token, "S11 (pairs_pairs_comma)")),
         };
-        Ok(self.pairs_pairs_comma_pair(pairs, pair)?)
+        Ok(self.pairs_pairs_comma_pair(build, pairs, pair)?)
     }
 
     /// S12= pairs = pairs ',' pair *
-    fn pairs_pairs_comma_pair(&mut self, pairs: Pairs, pair: Pair) -> Result<'source, Pairs> {
-        let Pairs(mut object) = pairs;
+    fn pairs_pairs_comma_pair<B: Build<'source>>(&mut self, build: &mut B, pairs: Pairs<B::Object>, pair: Pair<B::Key, B::Value>) -> Result<'source, Pairs<B::Object>> {
+        let Pairs(mut object, len) = pairs;
         let Pair((key, value)) = pair;
-        object.insert(key, value);
-        Ok(Pairs(object))
+        build.insert(&mut object, key, value);
+        Ok(Pairs(object, len + 1))
     }
 
     /// S13= object = '{' pairs '}' *
-    fn object_open_pairs_close(&mut self, pairs: Pairs) -> Result<'source, Object> {
-        let Pairs(object) = pairs;
+    fn object_open_pairs_close<O>(&mut self, pairs: Pairs<O>) -> Result<'source, Object<O>> {
+        let Pairs(object, _) = pairs;
         Ok(Object(object))
     }
 
     /// S14= object = '{' '}' *
-    fn object_open_close(&mut self) -> Result<'source, Object> {
-        let object = json::Object::new();
+    fn object_open_close<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Object<B::Object>> {
+        let object = build.object(0);
         Ok(Object(object))
     }
 
     /// S15= value = object *
-    fn value_object(&mut self, object: Object) -> Result<'source, Value> {
+    fn value_object<B: Build<'source>>(&mut self, build: &mut B, object: Object<B::Object>) -> Result<'source, Value<B::Value>> {
         let Object(object) = object;
-        let value = json::Value::Object(object);
+        let value = build.object_value(object);
         Ok(Value(value))
     }
 
@@ -266,55 +955,87 @@ impl<'source> Parse<'source> {
     ///      object = * '{' '}'
     ///      array = * '[' elements ']'
     ///      array = * '[' ']'
-    fn array_open(&mut self) -> Result<'source, Array> {
+    fn array_open<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Array<B::Array>> {
+        self.depth += 1;
+        self.record_depth();
+        self.push_path(|| Segment::Index(0));
         let token = self.lex.token();
+        let start = self.lex.offset(token.span);
+        self.record_type(&token.kind);
+        if token.kind != TokenKind::RightBracket {
+            if self.element_limit_reached() {
+                return Err(ParseError::new(token, ParseErrorKind::LimitExceeded, "S16 (array_open)"));
+            }
+            self.elements += 1;
+            self.record_comments(None);
+        }
         let value = match token {
-            Token { kind: TokenKind::String(string), .. } => self.value_string(string)?,
-            Token { kind: TokenKind::Number(number), .. } => self.value_number(number)?,
-            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(bool_)?,
-            Token { kind: TokenKind::Null, .. } => self.value_null()?,
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S16 (array_open)"));
+            }
             Token { kind: TokenKind::LeftBrace, .. } => {
-                let object = self.object_open()?;
-                self.value_object(object)?
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
             }
             Token { kind: TokenKind::LeftBracket, .. } => {
-                let array = self.array_open()?;
-                self.value_array(array)?
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
+            }
+            Token { kind: TokenKind::RightBracket, .. } => {
+                self.path.pop();
+                self.depth -= 1;
+                return Ok(self.array_open_close(build)?);
+            }
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S16 (array_open)"));
             }
-            Token { kind: TokenKind::RightBracket, .. } => return Ok(self.array_open_close()?),
-            _ => return Err(ParseError { token }),
+            _ => return Err(ParseError::unexpected(token, "S16 (array_open)")),
         };
-        let mut elements = self.elements_value(value)?;
+        self.record_span(start);
+        self.path.pop();
+        let mut elements = self.elements_value(build, value)?;
         loop {
-            match self.array_open_elements(elements)? {
+            match self.array_open_elements(build, elements)? {
                 Either::Left(e) => elements = e,
-                Either::Right(array) => return Ok(array),
+                Either::Right(array) => {
+                    self.depth -= 1;
+                    return Ok(array);
+                }
             }
         }
     }
 
     /// S17= elements = value *
-    fn elements_value(&mut self, value: Value) -> Result<'source, Elements> {
+    fn elements_value<B: Build<'source>>(&mut self, build: &mut B, value: Value<B::Value>) -> Result<'source, Elements<B::Array>> {
         let Value(value) = value;
-        let mut array = json::Array::new();
-        array.push(value);
-        Ok(Elements(array))
+        let capacity = self.capacity_hint(self.array_capacity);
+        let mut array = build.array(capacity);
+        build.push(&mut array, value);
+        Ok(Elements(array, 1))
     }
 
     /// S18= array = '[' elements * ']'
     ///      elements = elements * ',' value
-    fn array_open_elements(&mut self, elements: Elements) -> Result<'source, Either<Elements, Array>> {
+    fn array_open_elements<B: Build<'source>>(&mut self, build: &mut B, elements: Elements<B::Array>) -> Result<'source, MoreElements<B::Array>> {
         let token = self.lex.token();
         match token {
             Token { kind: TokenKind::Comma, .. } => {
-                let elements = self.elements_elements_comma(elements)?;
+                let elements = self.elements_elements_comma(build, elements)?;
                 Ok(Either::Left(elements))
             }
             Token { kind: TokenKind::RightBracket, .. } => {
                 let array = self.array_open_elements_close(elements)?;
                 Ok(Either::Right(array))
             }
-            _ => return Err(ParseError { token }),
+            _ => return Err(ParseError::unexpected(token, "S18 (array_open_elements)")),
         }
     }
 
@@ -329,61 +1050,1042 @@ impl<'source> Parse<'source> {
     ///      object = * '{' '}'
     ///      array = * '[' elements ']'
     ///      array = * '[' ']'
-    fn elements_elements_comma(&mut self, elements: Elements) -> Result<'source, Elements> {
+    fn elements_elements_comma<B: Build<'source>>(&mut self, build: &mut B, elements: Elements<B::Array>) -> Result<'source, Elements<B::Array>> {
+        self.push_path(|| Segment::Index(elements.1));
         let token = self.lex.token();
+        if self.element_limit_reached() {
+            return Err(ParseError::new(token, ParseErrorKind::LimitExceeded, "S19 (elements_elements_comma)"));
+        }
+        self.elements += 1;
+        let start = self.lex.offset(token.span);
+        self.record_type(&token.kind);
+        self.record_comments(None);
         let value = match token {
-            Token { kind: TokenKind::String(string), .. } => self.value_string(string)?,
-            Token { kind: TokenKind::Number(number), .. } => self.value_number(number)?,
-            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(bool_)?,
-            Token { kind: TokenKind::Null, .. } => self.value_null()?,
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S19 (elements_elements_comma)"));
+            }
             Token { kind: TokenKind::LeftBrace, .. } => {
-                let object = self.object_open()?;
-                self.value_object(object)?
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
             }
             Token { kind: TokenKind::LeftBracket, .. } => {
-                let array = self.array_open()?;
-                self.value_array(array)?
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
             }
-            _ => return Err(ParseError { token }),
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S19 (elements_elements_comma)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S19 (elements_elements_comma)")),
         };
-        Ok(self.elements_elements_comma_value(elements, value)?)
+        self.record_span(start);
+        self.path.pop();
+        Ok(self.elements_elements_comma_value(build, elements, value)?)
     }
 
     /// S20= elements = elements ',' value *
-    fn elements_elements_comma_value(&mut self, elements: Elements, value: Value) -> Result<'source, Elements> {
-        let Elements(mut array) = elements;
+    fn elements_elements_comma_value<B: Build<'source>>(&mut self, build: &mut B, elements: Elements<B::Array>, value: Value<B::Value>) -> Result<'source, Elements<B::Array>> {
+        let Elements(mut array, len) = elements;
         let Value(value) = value;
-        array.push(value);
-        Ok(Elements(array))
+        build.push(&mut array, value);
+        Ok(Elements(array, len + 1))
     }
 
     /// S21= array = '[' elements ']' *
-    fn array_open_elements_close(&mut self, elements: Elements) -> Result<'source, Array> {
-        let Elements(array) = elements;
+    fn array_open_elements_close<A>(&mut self, elements: Elements<A>) -> Result<'source, Array<A>> {
+        let Elements(array, _) = elements;
         Ok(Array(array))
     }
 
     /// S22= array = '[' ']' *
-    fn array_open_close(&mut self) -> Result<'source, Array> {
-        let array = json::Array::new();
+    fn array_open_close<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Array<B::Array>> {
+        let array = build.array(0);
         Ok(Array(array))
     }
 
     /// S23 = value = array *
-    fn value_array(&mut self, array: Array) -> Result<'source, Value> {
+    fn value_array<B: Build<'source>>(&mut self, build: &mut B, array: Array<B::Array>) -> Result<'source, Value<B::Value>> {
         let Array(array) = array;
-        let value = json::Value::Array(array);
+        let value = build.array_value(array);
         Ok(Value(value))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// S25= stream = * '[' elements ']'
+    ///      stream = * '[' ']'
+    fn stream_start<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Option<B::Value>> {
+        let rest = self.lex.remaining();
+        let token = self.lex.token();
+        self.check_edge_whitespace(rest, token.span, "S25 (stream_start)")?;
+        match token {
+            Token { kind: TokenKind::LeftBracket, .. } if self.depth_limit_reached() => {
+                Err(ParseError::new(token, ParseErrorKind::TooDeep, "S25 (stream_start)"))
+            }
+            Token { kind: TokenKind::LeftBracket, .. } => {
+                self.depth += 1;
+                self.record_depth();
+                Ok(self.stream_open(build)?)
+            }
+            _ => return Err(ParseError::unexpected(token, "S25 (stream_start)")),
+        }
+    }
 
-    #[test]
-    fn test() {
-        let s = r#"{ "foo": 3, "bar": ["baz", -5.8], "qux": 13e5 }"#;
-        assert!(Parse::new(s).value().is_ok());
+    /// S26= stream = '[' * elements ']'
+    ///      stream = '[' * ']'
+    ///      elements = * value
+    ///      elements = * elements ',' value
+    ///      value = * STRING
+    ///      value = * NUMBER
+    ///      value = * BOOL
+    ///      value = * NULL
+    ///      value = * object
+    ///      value = * array
+    ///      object = * '{' pairs '}'
+    ///      object = * '{' '}'
+    ///      array = * '[' elements ']'
+    ///      array = * '[' ']'
+    fn stream_open<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Option<B::Value>> {
+        let token = self.lex.token();
+        let value = match token {
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S26 (stream_open)"));
+            }
+            Token { kind: TokenKind::LeftBrace, .. } => {
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
+            }
+            Token { kind: TokenKind::LeftBracket, .. } => {
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
+            }
+            Token { kind: TokenKind::RightBracket, .. } => return Ok(self.stream_close()?),
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S26 (stream_open)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S26 (stream_open)")),
+        };
+        let Value(value) = value;
+        Ok(Some(value))
+    }
+
+    /// S27= stream = '[' elements * ']'
+    ///      elements = elements * ',' value
+    fn stream_elements<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Option<B::Value>> {
+        let token = self.lex.token();
+        match token {
+            Token { kind: TokenKind::Comma, .. } => Ok(self.stream_elements_comma(build)?),
+            Token { kind: TokenKind::RightBracket, .. } => Ok(self.stream_close()?),
+            _ => return Err(ParseError::unexpected(token, "S27 (stream_elements)")),
+        }
+    }
+
+    /// S28= elements = elements ',' * value
+    ///      value = * STRING
+    ///      value = * NUMBER
+    ///      value = * BOOL
+    ///      value = * NULL
+    ///      value = * object
+    ///      value = * array
+    ///      object = * '{' pairs '}'
+    ///      object = * '{' '}'
+    ///      array = * '[' elements ']'
+    ///      array = * '[' ']'
+    fn stream_elements_comma<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Option<B::Value>> {
+        let token = self.lex.token();
+        let value = match token {
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S28 (stream_elements_comma)"));
+            }
+            Token { kind: TokenKind::LeftBrace, .. } => {
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
+            }
+            Token { kind: TokenKind::LeftBracket, .. } => {
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
+            }
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S28 (stream_elements_comma)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S28 (stream_elements_comma)")),
+        };
+        let Value(value) = value;
+        Ok(Some(value))
+    }
+
+    /// S29= stream = '[' elements ']' *
+    ///      stream = '[' ']' *
+    fn stream_close<V>(&mut self) -> Result<'source, Option<V>> {
+        let rest = self.lex.remaining();
+        let token = self.lex.token();
+        self.check_edge_whitespace(rest, token.span, "S29 (stream_close)")?;
+        match token {
+            Token { kind: TokenKind::End, .. } => Ok(None),
+            _ => return Err(ParseError::unexpected(token, "S29 (stream_close)")),
+        }
+    }
+
+    /// S30= values = * value values
+    ///      values = *
+    ///      value = * STRING
+    ///      value = * NUMBER
+    ///      value = * BOOL
+    ///      value = * NULL
+    ///      value = * object
+    ///      value = * array
+    ///      object = * '{' pairs '}'
+    ///      object = * '{' '}'
+    ///      array = * '[' elements ']'
+    ///      array = * '[' ']'
+    fn values_start<B: Build<'source>>(&mut self, build: &mut B) -> Result<'source, Option<B::Value>> {
+        let token = self.lex.token();
+        let value = match token {
+            Token { kind: TokenKind::String(string), span } => self.value_string(build, span, string)?,
+            Token { kind: TokenKind::Number(number), .. } => self.value_number(build, number)?,
+            Token { kind: TokenKind::Integer(integer), .. } => self.value_integer(build, integer)?,
+            Token { kind: TokenKind::RawNumber(number), .. } => self.value_raw_number(build, number)?,
+            Token { kind: TokenKind::Bool(bool_), .. } => self.value_bool(build, bool_)?,
+            Token { kind: TokenKind::Null, .. } => self.value_null(build)?,
+            Token { kind: TokenKind::LeftBrace, .. } | Token { kind: TokenKind::LeftBracket, .. }
+                if self.depth_limit_reached() =>
+            {
+                return Err(ParseError::new(token, ParseErrorKind::TooDeep, "S30 (values_start)"));
+            }
+            Token { kind: TokenKind::LeftBrace, .. } => {
+                let object = self.object_open(build)?;
+                self.value_object(build, object)?
+            }
+            Token { kind: TokenKind::LeftBracket, .. } => {
+                let array = self.array_open(build)?;
+                self.value_array(build, array)?
+            }
+            Token { kind: TokenKind::End, .. } => return Ok(None),
+            Token { kind: TokenKind::Colon, .. } => {
+                return Err(ParseError::new(token, ParseErrorKind::UnexpectedColon, "S30 (values_start)"));
+            }
+            _ => return Err(ParseError::unexpected(token, "S30 (values_start)")),
+        };
+        let Value(value) = value;
+        Ok(Some(value))
+    }
+
+    /// Run a state that builds a `json::Value`, lending it the duplicate key callback.
+    fn build_value<T, F: FnOnce(&mut Self, &mut BuildValue<'source>) -> T>(&mut self, state: F) -> T {
+        let mut build = BuildValue { merge_keys: self.merge_keys.take() };
+        let result = state(self, &mut build);
+        self.merge_keys = build.merge_keys;
+        result
+    }
+
+    /// Whether an object with `keys` members is already at `Options::max_object_keys`.
+    fn key_limit_reached(&self, keys: usize) -> bool {
+        match self.options.max_object_keys {
+            Some(max) => keys >= max,
+            None => false,
+        }
+    }
+
+    /// Whether the parser is already inside `Options::max_depth` objects and arrays.
+    fn depth_limit_reached(&self) -> bool {
+        match self.options.max_depth {
+            Some(max) => self.depth >= max,
+            None => false,
+        }
+    }
+
+    /// With `!Options::allow_surrounding_whitespace`, reject any whitespace between `rest`, the
+    /// source before a token at the start or end of the document, and the token's `span`.
+    fn check_edge_whitespace(&self, rest: &'source str, span: &'source str, state: &'static str) -> Result<'source, ()> {
+        let len = span.as_ptr() as usize - rest.as_ptr() as usize;
+        if self.options.allow_surrounding_whitespace || len == 0 {
+            return Ok(());
+        }
+        let whitespace = &rest[..len];
+        let newlines = whitespace.matches('\n').count();
+        let token = Token { span: whitespace, kind: TokenKind::Whitespace(newlines) };
+        Err(ParseError::unexpected(token, state))
+    }
+
+    /// Whether the document already has `Options::max_elements` array elements and object
+    /// members, so another may not be started.
+    fn element_limit_reached(&self) -> bool {
+        match self.options.max_elements {
+            Some(max) => self.elements >= max,
+            None => false,
+        }
+    }
+
+    /// The capacity to start the current object or array with, given the hint for a top-level one.
+    fn capacity_hint(&self, hint: usize) -> usize {
+        if self.depth == 1 { hint } else { 0 }
+    }
+
+    /// Update `ParseStats::max_depth` after entering an object or array.
+    fn record_depth(&mut self) {
+        #[cfg(feature = "stats")]
+        {
+            self.max_depth = self.max_depth.max(self.depth);
+        }
+    }
+
+    /// Descend into an object member or array element, if spans, comments or types are being
+    /// recorded.
+    fn push_path<F: FnOnce() -> Segment>(&mut self, segment: F) {
+        if self.spans.is_some() || self.comments.is_some() || self.types.is_some() {
+            self.path.push(segment());
+        }
+    }
+
+    /// Record the span of the value that started at `start` and ends at the current position.
+    fn record_span(&mut self, start: usize) {
+        if let Some(ref mut spans) = self.spans {
+            spans.insert(pointer(&self.path, None, false), start..self.lex.position());
+        }
+    }
+
+    /// Record the kind of the current value from its first token. Tokens that do not start a
+    /// value, such as the `]` of an empty array, are ignored.
+    fn record_type(&mut self, token: &TokenKind) {
+        if let Some(ref mut types) = self.types {
+            let kind = match *token {
+                TokenKind::String(_) => json::ValueKind::String,
+                TokenKind::Number(_) | TokenKind::Integer(_) | TokenKind::RawNumber(_) => {
+                    json::ValueKind::Number
+                }
+                TokenKind::Bool(_) => json::ValueKind::Bool,
+                TokenKind::Null => json::ValueKind::Null,
+                TokenKind::LeftBrace => json::ValueKind::Object,
+                TokenKind::LeftBracket => json::ValueKind::Array,
+                _ => return,
+            };
+            types.entry(pointer(&self.path, None, true)).or_default().insert(kind);
+        }
+    }
+
+    /// Attach the comments before the last token to the current value, or to its member `key`.
+    fn record_comments(&mut self, key: Option<&str>) {
+        if let Some(ref mut comments) = self.comments {
+            let lexed = self.lex.comments();
+            if !lexed.is_empty() {
+                let pointer = pointer(&self.path, key, false);
+                comments.entry(pointer).or_default().extend(lexed.iter().map(|&comment| String::from(comment)));
+            }
+        }
+    }
+}
+
+/// The JSON Pointer to the value at `path`, or to its member `key`, with `*` in place of array
+/// indices if `wildcard` is set.
+fn pointer(path: &[Segment], key: Option<&str>, wildcard: bool) -> String {
+    let mut pointer = String::new();
+    for segment in path {
+        pointer.push('/');
+        match *segment {
+            Segment::Key(ref key) => pointer.push_str(key),
+            Segment::Index(_) if wildcard => pointer.push('*'),
+            Segment::Index(index) => pointer.push_str(&index.to_string()),
+        }
+    }
+    if let Some(key) = key {
+        pointer.push('/');
+        pointer.push_str(&json::escape_segment(key));
+    }
+    pointer
+}
+
+impl<'parse, 'source> Iterator for ArrayElements<'parse, 'source> {
+    type Item = Result<'source, json::Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = match self.state {
+            StreamState::Start => self.parse.build_value(|parse, build| parse.stream_start(build)),
+            StreamState::Elements => self.parse.build_value(|parse, build| parse.stream_elements(build)),
+            StreamState::Done => return None,
+        };
+        match element {
+            Ok(Some(value)) => {
+                self.state = StreamState::Elements;
+                Some(Ok(value))
+            }
+            Ok(None) => {
+                self.state = StreamState::Done;
+                None
+            }
+            Err(error) => {
+                self.state = StreamState::Done;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Check that a string is a single valid JSON value, without building it.
+pub fn validate<'source>(source: &'source str) -> Result<'source, ()> {
+    Parse::new(source).validate()
+}
+
+/// Check that a string is a single valid JSON value under the given options, without
+/// building it.
+///
+/// Set `Options::max_depth` and `Options::max_object_keys` to bound the work done on
+/// untrusted input.
+pub fn validate_with<'source>(source: &'source str, options: Options) -> Result<'source, ()> {
+    Parse::with_options(source, options).validate()
+}
+
+/// Decode a single JSON string literal, such as `"a\\nb"`, into its contents.
+///
+/// Returns an error if the literal is malformed or followed by anything but whitespace. As with
+/// `Options::strict`, invalid unicode escapes and unknown escapes are malformed.
+pub fn decode_string<'source>(source: &'source str) -> Result<'source, String> {
+    let mut lex = Lex::with_options(source, Options::strict());
+    let string = match lex.token() {
+        Token { kind: TokenKind::String(string), .. } => string,
+        token => return Err(ParseError::unexpected(token, "decode_string")),
+    };
+    match lex.token() {
+        Token { kind: TokenKind::End, .. } => Ok(string),
+        token => Err(ParseError::unexpected(token, "decode_string")),
+    }
+}
+
+/// Decode the contents of a JSON string literal, without the surrounding quotes, such as
+/// `a\nb`, with the same rules as the lexer.
+///
+/// As with the default `Options`, invalid unicode escapes become U+FFFD and unknown escapes
+/// are kept. Returns an error spanning the whole input if it has an unescaped quote or ends in
+/// a lone backslash, whose `lex_error` gives the reason. This is the inverse of
+/// `json::escape_json_string`.
+pub fn unescape_json_string<'source>(source: &'source str) -> Result<'source, String> {
+    let literal = format!("\"{}\"", source);
+    let mut lex = Lex::new(&literal);
+    match (lex.token().kind, lex.token().kind) {
+        (TokenKind::String(string), TokenKind::End) => Ok(string),
+        (first, _) => {
+            let kind = match first {
+                TokenKind::Error(kind) => kind,
+                _ => LexErrorKind::InvalidToken,
+            };
+            let token = Token { span: source, kind: TokenKind::Error(kind) };
+            Err(ParseError::unexpected(token, "unescape_json_string"))
+        }
+    }
+}
+
+/// Parse a string that is a single JSON value, with a fast path for scalars.
+///
+/// A lone string, number, boolean or null is read directly from the lexer. Objects and arrays
+/// fall back to the full parser.
+pub fn parse_scalar(source: &str) -> Option<json::Value> {
+    let mut lex = Lex::new(source);
+    let value = match lex.token().kind {
+        TokenKind::String(string) => json::Value::String(string),
+        TokenKind::Number(number) => json::Value::Number(number),
+        TokenKind::Integer(integer) => json::Value::Integer(integer),
+        TokenKind::RawNumber(number) => json::Value::RawNumber(number),
+        TokenKind::Bool(bool_) => json::Value::Bool(bool_),
+        TokenKind::Null => json::Value::Null,
+        _ => return Parse::new(source).value().ok(),
+    };
+    match lex.token().kind {
+        TokenKind::End => Some(value),
+        _ => None,
+    }
+}
+
+/// A reusable JSON parser, for parsing many values with fewer allocations.
+///
+/// Strings are decoded into buffers from a pool, which `recycle` refills from values that are
+/// no longer needed.
+pub struct Parser {
+    options: Options,
+    strings: Vec<String>,
+}
+
+impl Parser {
+    /// Create a new reusable parser, with an empty pool.
+    pub fn new() -> Self {
+        Self::with_options(Options::default())
+    }
+
+    /// Create a new reusable parser with the given options, with an empty pool.
+    pub fn with_options(options: Options) -> Self {
+        Parser { options, strings: Vec::new() }
+    }
+
+    /// Parse a JSON value, decoding its strings into pooled buffers while any remain.
+    pub fn parse_reuse<'source>(&mut self, source: &'source str) -> Result<'source, json::Value> {
+        let lex = Lex::with_strings(source, self.options, mem::take(&mut self.strings));
+        let mut parse = Parse::with_lex(lex, self.options);
+        let value = parse.value();
+        self.strings = parse.lex.into_strings();
+        value
+    }
+
+    /// Return the strings in a value, including object keys, to the pool.
+    pub fn recycle(&mut self, value: json::Value) {
+        match value {
+            json::Value::String(string) | json::Value::RawNumber(string) => self.recycle_string(string),
+            json::Value::Object(object) => {
+                for (key, value) in object {
+                    self.recycle_string(key);
+                    self.recycle(value);
+                }
+            }
+            json::Value::Array(array) => {
+                for value in array {
+                    self.recycle(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn recycle_string(&mut self, mut string: String) {
+        string.clear();
+        self.strings.push(string);
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::NumberMode;
+
+    #[test]
+    fn test() {
+        let s = r#"{ "foo": 3, "bar": ["baz", -5.8], "qux": 13e5 }"#;
+        assert!(Parse::new(s).value().is_ok());
+    }
+
+    #[test]
+    fn empty_containers() {
+        for &s in &["{}", "[]", "[{}]", r#"{"a":[]}"#, "[[],{}]", r#"{"a":{"b":{}}}"#] {
+            assert_eq!(Parse::new(s).value().unwrap().to_string(), s);
+        }
+
+        let empty = json::Value::Object(json::Object::new());
+        assert_eq!(Parse::new("{ }").value().unwrap(), empty);
+        assert_eq!(Parse::new(" [\n\t] ").value().unwrap(), json::Value::Array(json::Array::new()));
+        assert_eq!(Parse::new("[ { } ]").value().unwrap(), json::Value::Array(vec![empty]));
+        assert!(Parse::new("{,}").value().is_err());
+        assert!(Parse::new("[,]").value().is_err());
+    }
+
+    #[test]
+    fn nul() {
+        let s = r#"{"a\u0000b":"\u0000"}"#;
+        let value = Parse::new(s).value().unwrap();
+        assert_eq!(value, json::Value::object().insert("a\0b", "\0".into()).build());
+        assert_eq!(value.to_string(), s);
+    }
+
+    #[test]
+    fn bom() {
+        let s = "\u{FEFF}{}";
+        assert_eq!(Parse::new(s).value().unwrap(), json::Value::Object(json::Object::new()));
+        assert!(Parse::with_options(s, Options::strict()).value().is_err());
+    }
+
+    #[test]
+    fn max_object_keys() {
+        let options = Options { max_object_keys: Some(2), ..Options::default() };
+        let s = r#"{ "a": { "c": 1, "d": 2 }, "b": [{ "e": 3 }, {}] }"#;
+        assert!(Parse::with_options(s, options).value().is_ok());
+
+        let s = r#"[{}, { "a": 1, "b": 2, "c": 3 }]"#;
+        let error = Parse::with_options(s, options).value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::TooManyKeys);
+
+        let options = Options { max_object_keys: Some(0), ..Options::default() };
+        assert!(Parse::with_options("{}", options).value().is_ok());
+        let error = Parse::with_options(r#"{ "a": 1 }"#, options).value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::TooManyKeys);
+    }
+
+    #[test]
+    fn surrounding_whitespace() {
+        let options = Options { allow_surrounding_whitespace: false, ..Options::default() };
+        assert!(Parse::with_options(r#"{ "a": [1, 2] }"#, options).value().is_ok());
+        assert!(Parse::with_options("[ 1 ]", options).array_elements().all(|element| element.is_ok()));
+
+        let error = Parse::with_options(" {}", options).value().unwrap_err();
+        assert_eq!((error.kind(), error.span()), (ParseErrorKind::UnexpectedToken, " "));
+        assert_eq!(Parse::with_options("[]\r\n", options).document().unwrap_err().span(), "\r\n");
+        assert_eq!(Parse::with_options("1 ", options).value().unwrap_err().state(), "accept (goal_value)");
+        let mut parse = Parse::with_options("[1]\n", options);
+        assert!(parse.array_elements().last().unwrap().is_err());
+
+        assert!(Parse::new(" {} ").value().is_ok());
+    }
+
+    #[test]
+    fn max_elements() {
+        let options = Options { max_elements: Some(4), ..Options::default() };
+        assert!(Parse::with_options(r#"{ "a": [1, 2], "b": {} }"#, options).value().is_ok());
+
+        // The nested elements count, as well as the member that contains them.
+        let error = Parse::with_options(r#"{ "a": [1, 2], "b": [3] }"#, options).value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::LimitExceeded);
+        assert_eq!(error.span(), "3");
+
+        // The element over the limit is rejected before it is built.
+        let error = Parse::with_options("[1, 2, 3, 4, [5, 6]]", options).value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::LimitExceeded);
+        assert_eq!(error.span(), "[");
+        let error = Parse::with_options(r#"{"a": [1, 2, 3], "b": {}}"#, options).value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::LimitExceeded);
+        assert_eq!(error.span(), "\"b\"");
+
+        let options = Options { max_elements: Some(0), ..Options::default() };
+        assert!(Parse::with_options("[[], {}]", options).value().is_err());
+        assert!(Parse::with_options("[]", options).value().is_ok());
+    }
+
+    #[test]
+    fn array_elements() {
+        let mut parse = Parse::new(r#"[1, "a", [2, {}]]"#);
+        let elements: Vec<_> = parse.array_elements().map(|element| element.unwrap()).collect();
+        assert_eq!(elements, vec![
+            json::Value::Number(1.0),
+            json::Value::from("a"),
+            json::Value::array().push(2.0.into()).push(json::Value::object().build()).build(),
+        ]);
+
+        assert_eq!(Parse::new(" [ ] ").array_elements().count(), 0);
+
+        let mut parse = Parse::new("[1 2]");
+        let mut elements = parse.array_elements();
+        assert_eq!(elements.next().unwrap().unwrap(), json::Value::Number(1.0));
+        assert!(elements.next().unwrap().is_err());
+        assert!(elements.next().is_none());
+
+        assert!(Parse::new("[1] 2").array_elements().last().unwrap().is_err());
+        assert!(Parse::new("{}").array_elements().next().unwrap().is_err());
+    }
+
+    #[test]
+    fn error_span() {
+        assert_eq!(Parse::new(r#"{"a": tru}"#).value().unwrap_err().span(), "tru");
+        assert_eq!(Parse::new("[1, 2").value().unwrap_err().span(), "");
+        assert_eq!(Parse::new(r#"["a" "b"]"#).value().unwrap_err().span(), r#""b""#);
+        assert_eq!(Parse::new("[+5.0]").value().unwrap_err().span(), "+5.0");
+    }
+
+    #[test]
+    fn error_state() {
+        let error = Parse::new(r#"{"a": 1 "b": 2}"#).value().unwrap_err();
+        assert_eq!(error.state(), "S10 (object_open_pairs)");
+        assert!(format!("{:?}", error).ends_with("} in state S10 (object_open_pairs)"));
+
+        assert_eq!(Parse::new("[1,]").value().unwrap_err().state(), "S19 (elements_elements_comma)");
+        assert_eq!(Parse::new("1 2").value().unwrap_err().state(), "accept (goal_value)");
+    }
+
+    #[test]
+    fn unexpected_colon() {
+        for s in &[r#"{"a"::1}"#, "[:]", "[1, :]", ":"] {
+            let error = Parse::new(s).value().unwrap_err();
+            assert_eq!((error.kind(), error.span()), (ParseErrorKind::UnexpectedColon, ":"));
+        }
+        let error = Parse::new(":").document().unwrap_err();
+        assert_eq!((error.kind(), error.span()), (ParseErrorKind::UnexpectedColon, ":"));
+        let error = Parse::new("[:]").value().unwrap_err();
+        assert!(format!("{:?}", error).starts_with("unexpected `:` where a value was expected"));
+        assert_eq!(Parse::new(r#"{"a" 1}"#).value().unwrap_err().kind(), ParseErrorKind::UnexpectedToken);
+    }
+
+    #[test]
+    fn for_each_value() {
+        let mut values = Vec::new();
+        Parse::new("{\"a\": 1}\n[true] \"b\"2").for_each_value(|value| values.push(value)).unwrap();
+        assert_eq!(values, vec![
+            r#"{"a": 1}"#.parse::<json::Value>().unwrap(),
+            json::Value::Array(vec![json::Value::Bool(true)]),
+            json::Value::from("b"),
+            json::Value::Number(2.0),
+        ]);
+
+        let mut values = Vec::new();
+        let error = Parse::new("1 ] 2").for_each_value(|value| values.push(value)).unwrap_err();
+        assert_eq!((values, error.span()), (vec![json::Value::Number(1.0)], "]"));
+        assert!(Parse::new(" ").for_each_value(|_| panic!("expected no values")).is_ok());
+    }
+
+    #[test]
+    fn render() {
+        let s = "{\n  \"a\": ],\n}";
+        let error = Parse::new(s).value().unwrap_err();
+        assert_eq!(error.render(s), "error: unexpected token `]`\n --> 2:8\n  |\n2 |   \"a\": ],\n  |        ^\n");
+
+        let s = "[\"abc\", tru]";
+        let error = Parse::new(s).value().unwrap_err();
+        assert_eq!(error.render(s), "error: invalid token `tru`\n --> 1:9\n  |\n1 | [\"abc\", tru]\n  |         ^^^\n");
+
+        let s = "[1,\n\t2";
+        let error = Parse::new(s).value().unwrap_err();
+        assert_eq!(error.render(s), "error: unexpected end of input\n --> 2:3\n  |\n2 | \t2\n  | \t ^\n");
+
+        let s = "[1, 1.e3]";
+        let error = Parse::new(s).value().unwrap_err();
+        assert_eq!(error.render(s), "error: invalid number `1.e3`\n --> 1:5\n  |\n1 | [1, 1.e3]\n  |     ^^^^\n");
+        assert_eq!(error.render(&String::from(s)), "error: invalid number `1.e3`\n");
+    }
+
+    #[test]
+    fn lex_error() {
+        let error = Parse::new("[1, 1.e3]").value().unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::UnexpectedToken);
+        assert_eq!(error.lex_error(), Some(LexErrorKind::InvalidNumber));
+        let error = Parse::new("{\"a\": \"b").value().unwrap_err();
+        assert_eq!(error.lex_error(), Some(LexErrorKind::UnterminatedString));
+        assert_eq!(Parse::new("[1,]").value().unwrap_err().lex_error(), None);
+    }
+
+    #[test]
+    fn value_prefix() {
+        let mut parse = Parse::new(r#"{"a": [1]} | filter"#);
+        assert_eq!(parse.value_prefix().unwrap(), r#"{"a": [1]}"#.parse::<json::Value>().unwrap());
+        assert_eq!(parse.remaining(), " | filter");
+        assert_eq!(parse.consumed(), 10);
+
+        let mut parse = Parse::new("12 34");
+        assert_eq!(parse.value_prefix().unwrap(), json::Value::Number(12.0));
+        assert_eq!(parse.consumed(), 2);
+        assert_eq!(parse.value_prefix().unwrap(), json::Value::Number(34.0));
+        assert_eq!(parse.remaining(), "");
+        assert_eq!(parse.value_prefix().unwrap_err().span(), "");
+
+        assert_eq!(Parse::new("[1, }").value_prefix().unwrap_err().span(), "}");
+    }
+
+    #[test]
+    fn from_reader() {
+        let value = Parse::from_reader(&b"{ \"a\": [1] }"[..]).unwrap();
+        assert_eq!(value, r#"{ "a": [1] }"#.parse::<json::Value>().unwrap());
+
+        match Parse::from_reader(&b"[1,]"[..]).unwrap_err() {
+            ReadError::Parse(error) => assert_eq!(error.span, "]"),
+            error => panic!("expected a parse error, found {:?}", error),
+        }
+        assert_eq!(Parse::from_reader(&b"[1,]"[..]).unwrap_err().to_string(), "unexpected token `]`");
+        match Parse::from_reader(&b"\"\xFF\""[..]).unwrap_err() {
+            ReadError::Io(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidData),
+            error => panic!("expected an I/O error, found {:?}", error),
+        }
+    }
+
+    #[test]
+    fn parser() {
+        let mut parser = Parser::new();
+        let value = parser.parse_reuse(r#"{ "a": ["bc", 1], "d": "e" }"#).unwrap();
+        assert!(parser.strings.is_empty());
+
+        parser.recycle(value);
+        assert_eq!(parser.strings.len(), 4);
+        assert!(parser.strings.iter().all(|string| string.is_empty() && string.capacity() > 0));
+
+        let value = parser.parse_reuse(r#"["x", "y"]"#).unwrap();
+        assert_eq!(value, json::Value::Array(vec!["x".into(), "y".into()]));
+        assert_eq!(parser.strings.len(), 2);
+    }
+
+    #[test]
+    fn borrowed_value() {
+        let s = r#"{ "a": ["b", "c\"d"], "e\u0066": 1 }"#;
+        let value = Parse::new(s).borrowed_value().unwrap();
+        assert_eq!(value.clone().into_owned(), Parse::new(s).value().unwrap());
+
+        let object = match value {
+            json::BorrowedValue::Object(object) => object,
+            _ => panic!("expected an object"),
+        };
+        let key = object.keys().find(|key| *key == "a").unwrap();
+        assert!(match *key { Cow::Borrowed(_) => true, Cow::Owned(_) => false });
+        let key = object.keys().find(|key| *key == "ef").unwrap();
+        assert!(match *key { Cow::Borrowed(_) => false, Cow::Owned(_) => true });
+
+        let strings = match object["a"] {
+            json::BorrowedValue::Array(ref array) => array.clone(),
+            _ => panic!("expected an array"),
+        };
+        assert_eq!(strings, vec![
+            json::BorrowedValue::String(Cow::Borrowed("b")),
+            json::BorrowedValue::String(Cow::Owned(String::from("c\"d"))),
+        ]);
+        assert!(Parse::new(r#"{"a": "b}"#).borrowed_value().is_err());
+    }
+
+    #[test]
+    fn interned_value() {
+        let s = r#"[{ "id": 1, "name": "a" }, { "id": 2, "name": "b" }, { "i\u0064": 3 }]"#;
+        let value = Parse::new(s).interned_value().unwrap();
+        assert_eq!(value.clone().into_owned(), Parse::new(s).value().unwrap());
+
+        let records = match value {
+            json::InternedValue::Array(array) => array,
+            _ => panic!("expected an array"),
+        };
+        let ids: Vec<_> = records.iter()
+            .map(|record| match *record {
+                json::InternedValue::Object(ref object) => object.keys().find(|key| &***key == "id").unwrap().clone(),
+                _ => panic!("expected an object"),
+            })
+            .collect();
+        assert!(Rc::ptr_eq(&ids[0], &ids[1]) && Rc::ptr_eq(&ids[1], &ids[2]));
+    }
+
+    #[test]
+    fn validate() {
+        assert!(super::validate(r#"{ "a": [1, "b\n", {}], "c": null }"#).is_ok());
+        assert!(super::validate(r#"{ "a": [1, "b\n", {}], "c": }"#).is_err());
+        assert!(super::validate("[1] 2").is_err());
+
+        let options = Options { max_depth: Some(2), ..Options::default() };
+        assert!(validate_with("[[], {}]", options).is_ok());
+        let error = validate_with(r#"[{ "a": [] }]"#, options).unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::TooDeep);
+        assert_eq!(error.span(), "[");
+
+        let options = Options { max_depth: Some(0), ..Options::default() };
+        assert!(validate_with("1", options).is_ok());
+        assert_eq!(validate_with("{}", options).unwrap_err().kind(), ParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn decode_string() {
+        assert_eq!(super::decode_string(r#""a\nb\u00e9""#).unwrap(), "a\nbé");
+        assert_eq!(super::decode_string(r#" "" "#).unwrap(), "");
+        assert_eq!(super::decode_string(r#""a" "b""#).unwrap_err().span(), r#""b""#);
+        assert_eq!(super::decode_string(r#""abc"#).unwrap_err().span(), r#""abc"#);
+        assert_eq!(super::decode_string("12").unwrap_err().span(), "12");
+
+        for s in &[r#""\ud800""#, r#""\x20""#] {
+            let error = super::decode_string(s).unwrap_err();
+            assert_eq!((error.span(), error.lex_error()), (*s, Some(LexErrorKind::InvalidEscape)));
+        }
+    }
+
+    #[test]
+    fn unescape_json_string() {
+        assert_eq!(super::unescape_json_string(r#"a\nb\u00e9\""#).unwrap(), "a\nbé\"");
+        assert_eq!(super::unescape_json_string("").unwrap(), "");
+        // The default options replace invalid unicode escapes and keep unknown ones.
+        assert_eq!(super::unescape_json_string(r"\uD800\q").unwrap(), "\u{FFFD}\\q");
+        for &(s, kind) in &[(r#"a"b"#, LexErrorKind::InvalidToken), (r"a\", LexErrorKind::UnterminatedString)] {
+            let error = super::unescape_json_string(s).unwrap_err();
+            assert_eq!(error.span(), s);
+            assert_eq!(error.lex_error(), Some(kind));
+        }
+    }
+
+    #[test]
+    fn parse_scalar() {
+        assert_eq!(super::parse_scalar(" \"a\\nb\" "), Some(json::Value::String(String::from("a\nb"))));
+        assert_eq!(super::parse_scalar("-1.5e1"), Some(json::Value::Number(-15.0)));
+        assert_eq!(super::parse_scalar("true"), Some(json::Value::Bool(true)));
+        assert_eq!(super::parse_scalar("null"), Some(json::Value::Null));
+        assert_eq!(super::parse_scalar("[1, {}]"), Some(r#"[1, {}]"#.parse::<json::Value>().unwrap()));
+
+        assert_eq!(super::parse_scalar(""), None);
+        assert_eq!(super::parse_scalar("1 2"), None);
+        assert_eq!(super::parse_scalar("\"abc"), None);
+        assert_eq!(super::parse_scalar("[1,]"), None);
+    }
+
+    #[test]
+    fn max_depth() {
+        let options = Options { max_depth: Some(1), ..Options::default() };
+        assert!(Parse::with_options("[1, 2]", options).value().is_ok());
+        assert!(Parse::with_options(r#"{ "a": {} }"#, options).document().is_err());
+
+        let mut parse = Parse::with_options("[1, [2]]", options);
+        let elements: Vec<_> = parse.array_elements().collect();
+        assert_eq!(elements[0].as_ref().unwrap(), &json::Value::Number(1.0));
+        assert_eq!(elements[1].as_ref().unwrap_err().kind(), ParseErrorKind::TooDeep);
+    }
+
+    #[test]
+    fn capacity() {
+        let capacity = |value: &json::Value| match *value {
+            json::Value::Array(ref array) => array.capacity(),
+            json::Value::Object(ref object) => object.capacity(),
+            _ => panic!("expected an array or object"),
+        };
+        for s in &[r#"[[1], {"b": 1}]"#, r#"{"a": [1], "b": {"c": 1}}"#] {
+            let value = Parse::new(s).array_capacity(100).object_capacity(100).value().unwrap();
+            assert!(capacity(&value) >= 100);
+            let nested: Vec<_> = match value {
+                json::Value::Array(ref array) => array.iter().collect(),
+                json::Value::Object(ref object) => object.values().collect(),
+                _ => unreachable!(),
+            };
+            assert!(nested.iter().all(|&nested| capacity(nested) < 100));
+            assert_eq!(value, s.parse::<json::Value>().unwrap());
+        }
+    }
+
+    #[test]
+    fn merge_duplicate_keys() {
+        let s = r#"{ "cookie": "a", "cookie": "b", "other": 1, "cookie": "c" }"#;
+        let last = r#"{ "cookie": "c", "other": 1 }"#.parse::<json::Value>().unwrap();
+        assert_eq!(Parse::new(s).value().unwrap(), last);
+
+        let value = Parse::new(s)
+            .merge_duplicate_keys(|_, existing, value| match existing {
+                json::Value::Array(mut array) => {
+                    array.push(value);
+                    json::Value::Array(array)
+                }
+                existing => json::Value::Array(vec![existing, value]),
+            })
+            .value()
+            .unwrap();
+        assert_eq!(value, r#"{ "cookie": ["a", "b", "c"], "other": 1 }"#.parse::<json::Value>().unwrap());
+    }
+
+    #[test]
+    fn document() {
+        assert!(Parse::new("{}").document().is_ok());
+        assert!(Parse::new(r#" [1, "a"] "#).document().is_ok());
+        assert!(Parse::new(r#""a""#).document().is_err());
+        assert!(Parse::new("3").document().is_err());
+        assert!(Parse::new("null").document().is_err());
+    }
+
+    #[test]
+    fn raw_numbers() {
+        let s = r#"[1.000000000000000000001,123456789012345678901234567890,-0.5e-7]"#;
+        let options = Options { number_mode: NumberMode::Raw, ..Options::default() };
+        let value = Parse::with_options(s, options).value().unwrap();
+        assert_eq!(value.to_string(), s);
+    }
+
+    #[test]
+    fn integers() {
+        let options = Options { number_mode: NumberMode::Integer, ..Options::default() };
+        let value = Parse::with_options("[-9223372036854775808, 2.5, 1e2]", options).value().unwrap();
+        assert_eq!(value, json::Value::Array(vec![
+            json::Value::Integer(i64::MIN),
+            json::Value::Number(2.5),
+            json::Value::Number(100.0),
+        ]));
+
+        assert!(Parse::with_options("[9223372036854775808]", options).value().is_err());
+    }
+
+    #[test]
+    fn big_integers() {
+        let s = "[-9223372036854775809, 1234567890123456789012345678901234567890, 12, 0.5]";
+        let options = Options { number_mode: NumberMode::BigInteger, ..Options::default() };
+        let value = Parse::with_options(s, options).value().unwrap();
+        assert_eq!(value, json::Value::Array(vec![
+            json::Value::RawNumber(String::from("-9223372036854775809")),
+            json::Value::RawNumber(String::from("1234567890123456789012345678901234567890")),
+            json::Value::Integer(12),
+            json::Value::Number(0.5),
+        ]));
+        assert_eq!(value.to_string(), s.replace(", ", ","));
+    }
+
+    #[test]
+    fn comments() {
+        let s = "/* root */ {\n  // a\n  \"a/b\": /* value */ [1, /* two */ 2 /* dropped */],\n  \"c\": {}\n}";
+        let options = Options { allow_comments: true, ..Options::default() };
+        let (value, comments) = Parse::with_options(s, options).value_with_comments().unwrap();
+        assert_eq!(value, r#"{ "a/b": [1, 2], "c": {} }"#.parse::<json::Value>().unwrap());
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[""], ["/* root */"]);
+        assert_eq!(comments["/a~1b"], ["// a", "/* value */"]);
+        assert_eq!(comments["/a~1b/1"], ["/* two */"]);
+
+        let (_, comments) = Parse::with_options("[/* a */]", options).value_with_comments().unwrap();
+        assert!(comments.is_empty());
+        assert!(Parse::new("[/* a */]").value_with_comments().is_err());
+    }
+
+    #[test]
+    fn value_collecting_types() {
+        use json::ValueKind::*;
+
+        let s = r#"{"a": [1, "b", null], "c": {"d/e": true, "f": []}, "g": 1e3}"#;
+        let (value, types) = Parse::new(s).value_collecting_types().unwrap();
+        assert_eq!(value, s.parse::<json::Value>().unwrap());
+        let set = |kinds: &[json::ValueKind]| kinds.iter().cloned().collect::<HashSet<_>>();
+        let expected: Types = vec![
+            ("", set(&[Object])),
+            ("/a", set(&[Array])),
+            ("/a/*", set(&[Number, String, Null])),
+            ("/c", set(&[Object])),
+            ("/c/d~1e", set(&[Bool])),
+            ("/c/f", set(&[Array])),
+            ("/g", set(&[Number])),
+        ].into_iter().map(|(pointer, kinds)| (pointer.to_string(), kinds)).collect();
+        assert_eq!(types, expected);
+
+        let s = r#"[{"a": 1}, {"a": [true]}, [[false]]]"#;
+        let (_, types) = Parse::new(s).value_collecting_types().unwrap();
+        assert_eq!(types["/*"], set(&[Object, Array]));
+        assert_eq!(types["/*/a"], set(&[Number, Array]));
+        assert_eq!(types["/*/a/*"], set(&[Bool]));
+        assert_eq!(types["/*/*"], set(&[Array]));
+
+        let options = Options { number_mode: NumberMode::Raw, ..Options::default() };
+        let (_, types) = Parse::with_options("[]", options).value_collecting_types().unwrap();
+        assert_eq!(types.len(), 1);
+        let (_, types) = Parse::with_options("[1]", options).value_collecting_types().unwrap();
+        assert_eq!(types["/*"], set(&[Number]));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn value_with_stats() {
+        let (value, stats) = Parse::new(r#"{"a": [1, [2]], "b": {}}"#).value_with_stats().unwrap();
+        assert_eq!(value, r#"{"a": [1, [2]], "b": {}}"#.parse::<json::Value>().unwrap());
+        assert_eq!(stats, ParseStats { tokens: 16, max_depth: 3 });
+
+        let (_, stats) = Parse::new("null").value_with_stats().unwrap();
+        assert_eq!(stats, ParseStats { tokens: 1, max_depth: 0 });
+    }
+
+    #[test]
+    fn spans() {
+        let s = r#"{ "a": [1, { "b/~": true }], "c": "d" }"#;
+        let (_, spans) = Parse::new(s).value_with_spans().unwrap();
+        assert_eq!(spans.len(), 6);
+        assert_eq!(&s[spans[""].clone()], s);
+        assert_eq!(&s[spans["/a"].clone()], r#"[1, { "b/~": true }]"#);
+        assert_eq!(&s[spans["/a/0"].clone()], "1");
+        assert_eq!(&s[spans["/a/1"].clone()], r#"{ "b/~": true }"#);
+        assert_eq!(&s[spans["/a/1/b~1~0"].clone()], "true");
+        assert_eq!(&s[spans["/c"].clone()], r#""d""#);
     }
 }
@@ -0,0 +1,119 @@
+use json::{self, Value, Object};
+
+/// A single JSON Patch operation, as defined by RFC 6902.
+///
+/// Paths are JSON Pointers, as in `Spans`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+}
+
+impl PatchOp {
+    /// Convert this operation to its JSON representation, such as
+    /// `{"op":"remove","path":"/a/0"}`.
+    pub fn to_value(&self) -> Value {
+        let (op, path, value) = match *self {
+            PatchOp::Add { ref path, ref value } => ("add", path, Some(value)),
+            PatchOp::Remove { ref path } => ("remove", path, None),
+            PatchOp::Replace { ref path, ref value } => ("replace", path, Some(value)),
+        };
+        let mut object = Object::new();
+        object.insert(String::from("op"), op.into());
+        object.insert(String::from("path"), path.as_str().into());
+        if let Some(value) = value {
+            object.insert(String::from("value"), value.clone());
+        }
+        Value::Object(object)
+    }
+}
+
+impl Value {
+    /// Compute the JSON Patch operations that transform this value into `other`.
+    ///
+    /// Objects and arrays are compared member by member, so only the values that differ are
+    /// replaced. Array elements are never moved, only added or removed at the end.
+    pub fn diff(&self, other: &Value) -> Vec<PatchOp> {
+        let mut patch = Vec::new();
+        diff(self, other, &mut String::new(), &mut patch);
+        patch
+    }
+}
+
+fn diff(from: &Value, to: &Value, path: &mut String, patch: &mut Vec<PatchOp>) {
+    if from == to {
+        return;
+    }
+
+    let len = path.len();
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            // Visit keys in order so the patch is deterministic.
+            let added = to.keys().filter(|key| !from.contains_key(*key));
+            let mut keys: Vec<_> = from.keys().chain(added).collect();
+            keys.sort();
+            for key in keys {
+                push_segment(path, key);
+                match (from.get(key), to.get(key)) {
+                    (Some(from), Some(to)) => diff(from, to, path, patch),
+                    (Some(_), None) => patch.push(PatchOp::Remove { path: path.clone() }),
+                    (None, Some(to)) => patch.push(PatchOp::Add { path: path.clone(), value: to.clone() }),
+                    (None, None) => unreachable!(),
+                }
+                path.truncate(len);
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (index, (from, to)) in from.iter().zip(to).enumerate() {
+                push_segment(path, &index.to_string());
+                diff(from, to, path, patch);
+                path.truncate(len);
+            }
+
+            // Remove from the end so earlier indices stay valid.
+            for index in (to.len()..from.len()).rev() {
+                push_segment(path, &index.to_string());
+                patch.push(PatchOp::Remove { path: path.clone() });
+                path.truncate(len);
+            }
+            for (index, to) in to.iter().enumerate().skip(from.len()) {
+                push_segment(path, &index.to_string());
+                patch.push(PatchOp::Add { path: path.clone(), value: to.clone() });
+                path.truncate(len);
+            }
+        }
+        _ => patch.push(PatchOp::Replace { path: path.clone(), value: to.clone() }),
+    }
+}
+
+fn push_segment(path: &mut String, segment: &str) {
+    path.push('/');
+    path.push_str(&json::escape_segment(segment));
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Value;
+    use patch::PatchOp;
+
+    #[test]
+    fn diff() {
+        let from: Value = r#"{ "a": 1, "b": [1, 2, 3], "c/d": { "e": true }, "f": null }"#.parse().unwrap();
+        let to: Value = r#"{ "a": 2, "b": [1, 4], "c/d": { "e": true, "g": [] }, "h": "i" }"#.parse().unwrap();
+        assert_eq!(from.diff(&to), vec![
+            PatchOp::Replace { path: String::from("/a"), value: 2.0.into() },
+            PatchOp::Replace { path: String::from("/b/1"), value: 4.0.into() },
+            PatchOp::Remove { path: String::from("/b/2") },
+            PatchOp::Add { path: String::from("/c~1d/g"), value: Value::array().build() },
+            PatchOp::Remove { path: String::from("/f") },
+            PatchOp::Add { path: String::from("/h"), value: "i".into() },
+        ]);
+
+        assert_eq!(from.diff(&from), vec![]);
+        assert_eq!(Value::Null.diff(&to)[0], PatchOp::Replace { path: String::new(), value: to.clone() });
+
+        let op = PatchOp::Remove { path: String::from("/a/0") };
+        assert_eq!(op.to_value(), r#"{"op":"remove","path":"/a/0"}"#.parse::<Value>().unwrap());
+    }
+}
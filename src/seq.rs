@@ -0,0 +1,78 @@
+use std::str;
+use parse::{Parse, Result};
+use options::Options;
+use json;
+
+/// An iterator over the records of a JSON text sequence, created by `parse_seq`.
+pub struct Seq<'source> {
+    records: str::Split<'source, char>,
+    options: Options,
+}
+
+/// Parse a JSON text sequence, as defined by RFC 7464, with one value per record.
+///
+/// Each record begins with the record separator U+001E and ends with a newline. A record
+/// without its newline may have been cut off, so it is skipped rather than parsed, and the
+/// records after it are still read. Empty records are skipped too.
+pub fn parse_seq<'source>(source: &'source str) -> Seq<'source> {
+    parse_seq_with(source, Options::default())
+}
+
+/// Parse a JSON text sequence with the given options.
+pub fn parse_seq_with<'source>(source: &'source str, options: Options) -> Seq<'source> {
+    let mut records = source.split('\x1E');
+    // Anything before the first separator is not part of a record.
+    records.next();
+    Seq { records, options }
+}
+
+impl<'source> Iterator for Seq<'source> {
+    type Item = Result<'source, json::Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = self.records.next()?;
+            if record.is_empty() {
+                continue;
+            }
+            if !record.ends_with('\n') {
+                continue;
+            }
+            // The newline frames the record, and is not part of its JSON text.
+            let text = &record[..record.len() - 1];
+            return Some(Parse::with_options(text, self.options).value());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::Value;
+    use options::Options;
+
+    #[test]
+    fn parse_seq() {
+        let s = "\x1E{\"a\": 1}\n\x1E\x1E[true]\n\x1E{\"b\n\x1E2\n\x1E{\"c\": ";
+        let records: Vec<_> = super::parse_seq(s).collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].as_ref().unwrap(), &r#"{"a": 1}"#.parse::<Value>().unwrap());
+        assert_eq!(records[1].as_ref().unwrap(), &Value::array().push(true.into()).build());
+        assert!(records[2].is_err());
+        assert_eq!(records[3].as_ref().unwrap(), &Value::Number(2.0));
+
+        assert_eq!(super::parse_seq("").count(), 0);
+        assert_eq!(super::parse_seq("\x1E1").count(), 0);
+
+        // A truncated record in the middle does not end the sequence.
+        let records: Vec<_> = super::parse_seq("\x1E1\x1E2\n\x1E[3\x1E4\n").map(Result::unwrap).collect();
+        assert_eq!(records, vec![Value::Number(2.0), Value::Number(4.0)]);
+    }
+
+    #[test]
+    fn parse_seq_with() {
+        let options = Options { allow_surrounding_whitespace: false, ..Options::default() };
+        let records: Vec<_> = super::parse_seq_with("\x1E[1]\n\x1E2\n", options).map(Result::unwrap).collect();
+        assert_eq!(records, vec![Value::array().push(1.0.into()).build(), Value::Number(2.0)]);
+        assert!(super::parse_seq_with("\x1E 2\n", options).next().unwrap().is_err());
+    }
+}